@@ -0,0 +1,55 @@
+//! Measures the average frame time of rendering the castle scene at full
+//! resolution. Compare the SIMD and scalar rasterizers with
+//! `cargo bench` and `cargo bench --no-default-features`.
+
+use drawing::{load_from_obj_file, Camera, Draw, Lighting, Pipeline, Scene};
+use glam::{Vec2, Vec3A};
+use std::{f32::consts::PI, time::Instant};
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+const WARMUP_FRAMES: u32 = 5;
+const FRAMES: u32 = 50;
+
+fn main() {
+    let scene = Scene::from(load_from_obj_file("assets/castle/castle.obj").unwrap());
+    let mut camera = Camera::new(Vec3A::new(0.0, 3.0, -4.0), Vec2::new(0.0, PI));
+    camera.aspect_ratio = WIDTH as f32 / HEIGHT as f32;
+    let size = Vec2::new(WIDTH as f32, HEIGHT as f32);
+
+    let lighting = Lighting::default();
+    let mut draw = Draw::new(WIDTH, HEIGHT, scene.materials.clone());
+    draw.clear_depth_buffer();
+    let mut frame = vec![0; 4 * WIDTH * HEIGHT];
+    let mut pipeline = Pipeline::new();
+
+    let mut render_frame = || {
+        frame.fill(0);
+        pipeline.render(&mut frame, &mut draw, &scene, &camera, &lighting, size);
+    };
+
+    for _ in 0..WARMUP_FRAMES {
+        render_frame();
+    }
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        render_frame();
+    }
+    let frame_time = start.elapsed() / FRAMES;
+
+    let rasterizer = if cfg!(feature = "simd") {
+        "simd"
+    } else {
+        "scalar"
+    };
+    let stats = draw.stats();
+    println!(
+        "castle {WIDTH}x{HEIGHT} ({rasterizer}): {frame_time:.2?} per frame, {} of {} triangles occluded",
+        stats.occluded, stats.triangles
+    );
+    let stats = pipeline.stats();
+    println!(
+        "{} vertices transformed for {} triangle corners",
+        stats.vertices, stats.corners
+    );
+}
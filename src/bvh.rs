@@ -0,0 +1,224 @@
+//! Bounding volumes and a hierarchy of them over a mesh's triangles, used to
+//! skip whole groups of triangles outside the view and to visit the rest from
+//! front to back.
+
+use crate::{geometry::Vertex, pipeline::CLIPPING_PLANES};
+use glam::{Mat4, Vec3A, Vec4};
+use std::ops::Range;
+
+/// Most triangles kept in a leaf of a [`Bvh`].
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// An axis-aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    /// Corner with the smallest coordinates.
+    pub min: Vec3A,
+    /// Corner with the largest coordinates.
+    pub max: Vec3A,
+}
+
+impl Aabb {
+    /// A box containing nothing, which grows to fit whatever it is joined with.
+    pub const EMPTY: Self = Self {
+        min: Vec3A::INFINITY,
+        max: Vec3A::NEG_INFINITY,
+    };
+
+    /// Returns the smallest box containing every point in `points`.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3A>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, point| Self {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        })
+    }
+
+    /// Returns the smallest box containing both `self` and `other`.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Returns the point in the middle of the box.
+    pub fn center(&self) -> Vec3A {
+        0.5 * (self.min + self.max)
+    }
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+/// The planes bounding the volume a camera can see, facing inwards.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    planes: [Vec4; CLIPPING_PLANES.len()],
+}
+
+impl Frustum {
+    /// Returns the frustum of `matrix`, transforming points into clip space,
+    /// in the space it transforms from. For a view projection matrix that is
+    /// world space, and for its product with a model matrix, model space.
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let transpose = matrix.transpose();
+        Self {
+            planes: CLIPPING_PLANES.map(|plane| transpose * plane),
+        }
+    }
+
+    /// Returns whether `aabb` lies entirely outside of the frustum. Boxes
+    /// near its corners may be kept even though they are outside.
+    pub fn excludes(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().any(|plane| {
+            let normal = Vec3A::from_vec4(*plane);
+            // The corner furthest along the normal is the last to leave the plane
+            let corner = Vec3A::select(normal.cmpge(Vec3A::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w < 0.0
+        })
+    }
+}
+
+/// A node of a [`Bvh`].
+#[derive(Clone, Debug)]
+struct Node {
+    aabb: Aabb,
+    /// Index of the first child, the second following it, or of the first
+    /// triangle of a leaf in [`Bvh::triangles`].
+    start: u32,
+    /// Number of triangles in a leaf, or zero for an inner node.
+    count: u32,
+}
+
+/// A bounding volume hierarchy over the triangles of a mesh, built once when
+/// the mesh is created. Each node bounds the triangles below it, so a node
+/// outside of the view frustum rules out all of them at once.
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Indices of the mesh's triangles, ordered so that each leaf refers to a
+    /// contiguous range.
+    triangles: Vec<u32>,
+}
+
+impl Bvh {
+    /// Builds a hierarchy over the triangles given by `indices` into
+    /// `vertices`, splitting each node in half along the longest axis of its
+    /// triangles' centroids.
+    pub fn new(vertices: &[Vertex], indices: &[[u32; 3]]) -> Self {
+        let bounds: Vec<Aabb> = indices
+            .iter()
+            .map(|triangle| {
+                Aabb::from_points(triangle.iter().map(|&i| vertices[i as usize].position))
+            })
+            .collect();
+        let mut bvh = Self {
+            nodes: vec![],
+            triangles: (0..indices.len() as u32).collect(),
+        };
+        if !indices.is_empty() {
+            bvh.nodes.push(Node {
+                aabb: Aabb::EMPTY,
+                start: 0,
+                count: 0,
+            });
+            bvh.build(0, 0..indices.len(), &bounds);
+        }
+        bvh
+    }
+
+    /// Fills in the node at `index` to cover `range` of the triangles,
+    /// recursively splitting it until the leaves are small enough.
+    fn build(&mut self, index: usize, range: Range<usize>, bounds: &[Aabb]) {
+        let triangles = &mut self.triangles[range.clone()];
+        let aabb = triangles
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, &i| aabb.union(&bounds[i as usize]));
+        self.nodes[index].aabb = aabb;
+        if triangles.len() <= MAX_LEAF_TRIANGLES {
+            self.nodes[index].start = range.start as u32;
+            self.nodes[index].count = triangles.len() as u32;
+            return;
+        }
+
+        let centroids = Aabb::from_points(triangles.iter().map(|&i| bounds[i as usize].center()));
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let middle = triangles.len() / 2;
+        triangles.select_nth_unstable_by(middle, |&a, &b| {
+            let [a, b] = [a, b].map(|i| bounds[i as usize].center()[axis]);
+            a.total_cmp(&b)
+        });
+
+        let first_child = self.nodes.len();
+        self.nodes[index].start = first_child as u32;
+        for _ in 0..2 {
+            self.nodes.push(Node {
+                aabb: Aabb::EMPTY,
+                start: 0,
+                count: 0,
+            });
+        }
+        let middle = range.start + middle;
+        self.build(first_child, range.start..middle, bounds);
+        self.build(first_child + 1, middle..range.end, bounds);
+    }
+
+    /// Returns the box bounding every triangle, or [`Aabb::EMPTY`] if there
+    /// are none.
+    pub fn aabb(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |root| root.aabb)
+    }
+
+    /// Calls `visit` with the indices of the triangles in each leaf that is
+    /// not entirely outside of `frustum`, visiting leaves nearer to `eye`
+    /// first, with both in the space the hierarchy was built in.
+    pub fn traverse(&self, frustum: &Frustum, eye: Vec3A, mut visit: impl FnMut(&[u32])) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        // Nodes are split at their median triangle wherever the triangles are,
+        // so each child holds at most half of its parent's triangles rounded
+        // up. With fewer than 2^32 triangles and leaves of up to four, no leaf
+        // is more than 30 levels deep. Each level leaves at most its farther
+        // child waiting on the stack, so it never holds more than 32 nodes.
+        let mut stack = [0; 2 * u32::BITS as usize];
+        let mut length = 1;
+        while length > 0 {
+            length -= 1;
+            let node = &self.nodes[stack[length]];
+            if frustum.excludes(&node.aabb) {
+                continue;
+            }
+            let start = node.start as usize;
+            if node.count > 0 {
+                visit(&self.triangles[start..start + node.count as usize]);
+                continue;
+            }
+            let [near, far] = if eye.distance_squared(self.nodes[start].aabb.center())
+                <= eye.distance_squared(self.nodes[start + 1].aabb.center())
+            {
+                [start, start + 1]
+            } else {
+                [start + 1, start]
+            };
+            debug_assert!(
+                length + 2 <= stack.len(),
+                "The hierarchy is deeper than its median splits allow"
+            );
+            // The stack is last in, first out, so the nearer child goes on top
+            stack[length] = far;
+            stack[length + 1] = near;
+            length += 2;
+        }
+    }
+}
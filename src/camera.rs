@@ -1,6 +1,7 @@
+//! A free-flying first-person camera and its projection.
+
 use glam::{Mat4, Vec2, Vec3, Vec3A, Vec4};
 use std::f32::consts::{FRAC_PI_2, TAU};
-use win_loop::winit::keyboard::KeyCode;
 
 const MIN_PITCH: f32 = 0.99 * -FRAC_PI_2;
 const MAX_PITCH: f32 = 0.99 * FRAC_PI_2;
@@ -8,14 +9,39 @@ const Z_NEAR: f32 = 0.1;
 const Z_FAR: f32 = 20_000.0;
 const SPEED: f32 = 0.1;
 const SENSITIVITY: f32 = 0.003;
-const FOV: f32 = FRAC_PI_2;
+const DEFAULT_FOV: f32 = FRAC_PI_2;
+
+/// A direction the camera can be moved in, relative to where it is looking.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Movement {
+    /// Towards the view direction, parallel to the ground.
+    Forward,
+    /// Away from the view direction, parallel to the ground.
+    Backward,
+    /// Perpendicular to the view direction, to the left.
+    Left,
+    /// Perpendicular to the view direction, to the right.
+    Right,
+    /// Along the world's positive Y axis.
+    Up,
+    /// Along the world's negative Y axis.
+    Down,
+}
 
+/// A first-person perspective camera.
+#[derive(Copy, Clone, Debug)]
 pub struct Camera {
+    /// Position of the camera in world space.
     pub position: Vec3A,
     rotation: Vec2,
+    /// Width of the viewport divided by its height.
     pub aspect_ratio: f32,
+    /// Vertical field of view in radians.
+    pub fov: f32,
 }
 
+/// Builds a right-handed perspective projection matrix with reversed depth,
+/// so that more precision is left for distant geometry.
 pub fn perspective_rh_reversed(
     fov_y_radians: f32,
     aspect_ratio: f32,
@@ -36,16 +62,19 @@ pub fn perspective_rh_reversed(
 }
 
 impl Camera {
+    /// Creates a camera at `position`, looking in the direction given by
+    /// `rotation` as (pitch, yaw) in radians.
     pub fn new(position: Vec3A, rotation: Vec2) -> Self {
         Camera {
             position,
             rotation,
             aspect_ratio: 0.0,
+            fov: DEFAULT_FOV,
         }
     }
 
     fn projection_matrix(&self) -> Mat4 {
-        perspective_rh_reversed(FOV, self.aspect_ratio, Z_NEAR, Z_FAR)
+        perspective_rh_reversed(self.fov, self.aspect_ratio, Z_NEAR, Z_FAR)
     }
 
     fn rotation_matrix(&self) -> Mat4 {
@@ -68,11 +97,13 @@ impl Camera {
         self.rotation_matrix() * self.translation_matrix()
     }
 
+    /// Returns the matrix transforming world space into clip space.
     pub fn view_projection_matrix(&self) -> Mat4 {
         self.projection_matrix() * self.view_matrix()
     }
 
-    pub fn update(&mut self, keys: &[KeyCode]) {
+    /// Moves the camera by one step in each of the given directions.
+    pub fn update(&mut self, movements: &[Movement]) {
         let mut translation = Vec3A::ZERO;
 
         let camera_matrix = self.view_matrix().inverse();
@@ -81,21 +112,21 @@ impl Camera {
             .with_y(0.0)
             .normalize();
 
-        for key in keys {
-            match key {
-                KeyCode::KeyW => translation += forward,
-                KeyCode::KeyS => translation -= forward,
-                KeyCode::KeyA => translation -= right,
-                KeyCode::KeyD => translation += right,
-                KeyCode::Space => translation.y += 1.0,
-                KeyCode::ShiftLeft => translation.y -= 1.0,
-                _ => (),
+        for movement in movements {
+            match movement {
+                Movement::Forward => translation += forward,
+                Movement::Backward => translation -= forward,
+                Movement::Left => translation -= right,
+                Movement::Right => translation += right,
+                Movement::Up => translation.y += 1.0,
+                Movement::Down => translation.y -= 1.0,
             }
         }
 
         self.position += translation * SPEED;
     }
 
+    /// Turns the camera by a mouse movement of `delta` as (pitch, yaw).
     pub fn update_rotation(&mut self, delta: Vec2) {
         self.rotation += delta * SENSITIVITY;
         self.rotation.x = self.rotation.x.clamp(MIN_PITCH, MAX_PITCH);
@@ -0,0 +1,82 @@
+use clap::Parser;
+use glam::{Vec2, Vec3A};
+use std::path::PathBuf;
+
+/// A basic software 3D renderer for OBJ, glTF, STL and PLY files.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Path of the OBJ, glTF, STL or PLY model file, or TOML scene file, to display
+    #[arg(default_value = "assets/castle/castle.obj")]
+    pub model: PathBuf,
+
+    /// Window width in pixels
+    #[arg(long, default_value_t = 800)]
+    pub width: u32,
+
+    /// Window height in pixels
+    #[arg(long, default_value_t = 600)]
+    pub height: u32,
+
+    /// Downscaling factor of the rendered image (higher is faster, 1 is full resolution) [default: 8]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub scale: Option<u32>,
+
+    /// Open a regular window instead of going fullscreen
+    #[arg(long)]
+    pub windowed: bool,
+
+    /// Initial camera position as "x,y,z", overriding the scene file [default: 0,3,-4]
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub position: Option<Vec3A>,
+
+    /// Initial camera pitch and yaw in degrees as "pitch,yaw", overriding the scene file [default: 0,180]
+    #[arg(long, value_parser = parse_vec2, allow_hyphen_values = true)]
+    pub rotation: Option<Vec2>,
+
+    /// Vertical field of view in degrees, overriding the scene file [default: 90]
+    #[arg(long)]
+    pub fov: Option<f32>,
+
+    /// Background color as "r,g,b" or "r,g,b,a" with components from 0 to 255, overriding the scene file [default: 110,177,255]
+    #[arg(long, value_parser = parse_color)]
+    pub clear_color: Option<[u8; 4]>,
+
+    /// Leave triangles crossing the sides of the screen to the rasterizer instead of clipping them
+    #[arg(long)]
+    pub guard_band: bool,
+
+    /// Render a single frame to this image file instead of opening a window
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+fn parse_floats<const N: usize>(string: &str) -> Result<[f32; N], String> {
+    let floats = string
+        .split(',')
+        .map(|s| s.trim().parse::<f32>().map_err(|e| format!("\"{s}\": {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    floats
+        .try_into()
+        .map_err(|floats: Vec<_>| format!("Expected {N} values, got {}", floats.len()))
+}
+
+fn parse_vec3(string: &str) -> Result<Vec3A, String> {
+    parse_floats(string).map(Vec3A::from_array)
+}
+
+fn parse_vec2(string: &str) -> Result<Vec2, String> {
+    parse_floats(string).map(Vec2::from_array)
+}
+
+fn parse_color(string: &str) -> Result<[u8; 4], String> {
+    let channels = string
+        .split(',')
+        .map(|s| s.trim().parse::<u8>().map_err(|e| format!("\"{s}\": {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    match *channels.as_slice() {
+        [r, g, b] => Ok([r, g, b, 255]),
+        [r, g, b, a] => Ok([r, g, b, a]),
+        _ => Err(format!("Expected 3 or 4 values, got {}", channels.len())),
+    }
+}
@@ -1,49 +1,645 @@
-use crate::{geometry::Triangle, mesh::Texture};
-use glam::{FloatExt, Vec2, Vec3A};
-use std::{collections::HashMap, f32::consts::FRAC_1_SQRT_2};
+//! Rasterization of screen-space triangles into a frame buffer.
 
-const LIGHT_ANGLE: Vec3A = Vec3A::new(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0);
-const LIGHT_MIN: f32 = 0.75;
-const LIGHT_MAX: f32 = 1.00;
+use crate::{
+    geometry::ScreenTriangle,
+    light::Lighting,
+    material::{Material, Materials},
+};
+use glam::{I64Vec2, Vec2, Vec3A, Vec4};
+use rayon::prelude::*;
+#[cfg(feature = "simd")]
+use std::simd::{cmp::SimdPartialOrd, num::SimdInt, Simd, StdFloat};
 
-pub struct Draw {
+const TILE_SIZE: usize = 64;
+const DEFAULT_ALPHA_CUTOFF: f32 = 0.5;
+/// Width and height of the groups of pixels tested against a triangle at once,
+/// to skip coverage tests for pixels entirely inside or outside of it.
+const BLOCK_SIZE: usize = 8;
+/// Number of fractional bits of the fixed-point screen coordinates.
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_STEP: i64 = 1 << SUBPIXEL_BITS;
+
+/// A rectangular region of the screen, rasterized independently of the
+/// others into its own color and depth buffers.
+struct Tile {
+    x: usize,
+    y: usize,
     width: usize,
     height: usize,
+    color: Vec<u8>,
     depth_buffer: Vec<f32>,
-    pub textures: HashMap<String, Texture>,
+    /// Farthest depth in each block of the depth buffer, the lower level of a
+    /// two-level depth pyramid used to skip hidden triangles and blocks.
+    block_depths: Vec<f32>,
+    /// Farthest depth in the whole tile, the top level of the pyramid.
+    farthest_depth: f32,
+    /// Indices of the triangles overlapping this tile, in drawing order.
+    triangles: Vec<usize>,
+    /// Indices of the triangles skipped for being entirely hidden within
+    /// this tile.
+    occluded: Vec<usize>,
 }
 
-impl Draw {
-    pub fn new(width: usize, height: usize, textures: HashMap<String, Texture>) -> Self {
-        Draw {
+impl Tile {
+    fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Tile {
+            x,
+            y,
             width,
             height,
+            color: vec![0; 4 * width * height],
             depth_buffer: vec![f32::MAX; width * height],
-            textures,
+            block_depths: vec![f32::MAX; width.div_ceil(BLOCK_SIZE) * height.div_ceil(BLOCK_SIZE)],
+            farthest_depth: f32::MAX,
+            triangles: vec![],
+            occluded: vec![],
         }
     }
 
-    fn pixel(&mut self, frame: &mut [u8], x: usize, y: usize, z: f32, rgba: [u8; 4]) {
-        // TODO: Actual transparency handling
-        if rgba[3] == 0 {
-            return;
+    /// Resets the depth buffer and the pyramid above it to `depth`.
+    fn clear_depth(&mut self, depth: f32) {
+        self.depth_buffer.fill(depth);
+        self.block_depths.fill(depth);
+        self.farthest_depth = depth;
+    }
+
+    /// Returns the index in `block_depths` of the block containing the pixel
+    /// at (`x`, `y`).
+    fn block_index(&self, x: usize, y: usize) -> usize {
+        (x - self.x) / BLOCK_SIZE + (y - self.y) / BLOCK_SIZE * self.width.div_ceil(BLOCK_SIZE)
+    }
+
+    /// Recomputes the farthest depth of the block containing the pixel at
+    /// (`x`, `y`) after pixels in it were drawn.
+    fn update_block_depth(&mut self, x: usize, y: usize) {
+        let x_start = (x - self.x) / BLOCK_SIZE * BLOCK_SIZE;
+        let y_start = (y - self.y) / BLOCK_SIZE * BLOCK_SIZE;
+        let x_end = (x_start + BLOCK_SIZE).min(self.width);
+        let y_end = (y_start + BLOCK_SIZE).min(self.height);
+        let farthest = (y_start..y_end)
+            .flat_map(|row| {
+                &self.depth_buffer[row * self.width + x_start..row * self.width + x_end]
+            })
+            .fold(f32::MIN, |farthest, &depth| farthest.max(depth));
+        let index = self.block_index(x, y);
+        self.block_depths[index] = farthest;
+    }
+
+    /// Returns whether the part of `triangle` within `bounding_box` is
+    /// certainly behind what was already drawn in this tile, checking the
+    /// farthest depth of the whole tile before that of each block.
+    fn is_occluded(&self, triangle: &ScreenTriangle, bounding_box: [usize; 4]) -> bool {
+        let nearest = nearest_depth(triangle);
+        if nearest > self.farthest_depth {
+            return true;
+        }
+        let [x_min, x_max, y_min, y_max] = self.clamp(bounding_box);
+        (y_min / BLOCK_SIZE * BLOCK_SIZE..=y_max)
+            .step_by(BLOCK_SIZE)
+            .all(|block_y| {
+                (x_min / BLOCK_SIZE * BLOCK_SIZE..=x_max)
+                    .step_by(BLOCK_SIZE)
+                    .all(|block_x| {
+                        nearest
+                            > self.block_depths
+                                [self.block_index(block_x.max(x_min), block_y.max(y_min))]
+                    })
+            })
+    }
+
+    /// Restricts a bounding box to the pixels of this tile.
+    fn clamp(&self, [x_min, x_max, y_min, y_max]: [usize; 4]) -> [usize; 4] {
+        [
+            x_min.max(self.x),
+            x_max.min(self.x + self.width - 1),
+            y_min.max(self.y),
+            y_max.min(self.y + self.height - 1),
+        ]
+    }
+
+    /// Copies this tile's region of `frame` into its color buffer.
+    fn load(&mut self, frame: &[u8], frame_width: usize) {
+        let length = 4 * self.width;
+        for row in 0..self.height {
+            let start = 4 * ((self.y + row) * frame_width + self.x);
+            self.color[row * length..(row + 1) * length]
+                .copy_from_slice(&frame[start..start + length]);
         }
-        let index = x + y * self.width;
+    }
+
+    /// Copies this tile's color buffer back into its region of `frame`.
+    fn store(&self, frame: &mut [u8], frame_width: usize) {
+        let length = 4 * self.width;
+        for row in 0..self.height {
+            let start = 4 * ((self.y + row) * frame_width + self.x);
+            frame[start..start + length]
+                .copy_from_slice(&self.color[row * length..(row + 1) * length]);
+        }
+    }
+
+    /// Draws a pixel if it is in front of what was drawn before. Blended
+    /// pixels are mixed with the existing color according to their alpha and
+    /// leave the depth buffer untouched, so that what is behind them can still
+    /// be drawn.
+    fn pixel(&mut self, x: usize, y: usize, z: f32, rgba: [u8; 4], blend: bool) {
+        let index = (x - self.x) + (y - self.y) * self.width;
         let Some(depth) = self.depth_buffer.get_mut(index) else {
             return;
         };
         if z > *depth {
             return;
         }
-        *depth = z;
+        if !blend {
+            *depth = z;
+        }
+
+        if let Some(slice) = self.color.get_mut(4 * index..4 * index + 4) {
+            if blend {
+                let alpha = f32::from(rgba[3]) / 255.0;
+                for (destination, source) in slice[0..3].iter_mut().zip(rgba) {
+                    *destination = f32::from(source)
+                        .mul_add(alpha, f32::from(*destination) * (1.0 - alpha))
+                        as u8;
+                }
+                slice[3] = rgba[3].saturating_add((f32::from(slice[3]) * (1.0 - alpha)) as u8);
+            } else {
+                slice.copy_from_slice(&rgba);
+            }
+        }
+    }
+
+    fn fill_triangle(
+        &mut self,
+        triangle: &ScreenTriangle,
+        [x_min, x_max, y_min, y_max]: [usize; 4],
+        material: &Material,
+        lighting: &Lighting,
+        eye: Vec3A,
+        alpha_cutoff: u8,
+    ) {
+        let Some(setup) = TriangleSetup::new(triangle, material, lighting, eye, alpha_cutoff)
+        else {
+            return;
+        };
+
+        let nearest = nearest_depth(triangle);
+        let [x_min, x_max, y_min, y_max] = self.clamp([x_min, x_max, y_min, y_max]);
+
+        for block_y in (y_min / BLOCK_SIZE * BLOCK_SIZE..=y_max).step_by(BLOCK_SIZE) {
+            'blocks: for block_x in (x_min / BLOCK_SIZE * BLOCK_SIZE..=x_max).step_by(BLOCK_SIZE) {
+                let (x_start, x_end) = (block_x.max(x_min), (block_x + BLOCK_SIZE - 1).min(x_max));
+                let (y_start, y_end) = (block_y.max(y_min), (block_y + BLOCK_SIZE - 1).min(y_max));
+                if nearest > self.block_depths[self.block_index(x_start, y_start)] {
+                    continue;
+                }
+
+                // Edge functions are linear, so their extremes over the block are at its corners
+                let mut fully_covered = true;
+                for edge in &setup.edges {
+                    let [min, max] = edge.range(x_start, x_end, y_start, y_end);
+                    if max < 0 {
+                        continue 'blocks;
+                    }
+                    fully_covered &= min >= 0;
+                }
+
+                // Blocks may be skipped, so only their first row is evaluated directly
+                let mut row = setup.edges.map(|edge| edge.at(x_start, y_start));
+                for y in y_start..=y_end {
+                    self.fill_span(&setup, row, y, x_start, x_end, fully_covered);
+                    for (value, edge) in row.iter_mut().zip(&setup.edges) {
+                        *value += edge.step_y;
+                    }
+                }
+                if !setup.blend {
+                    self.update_block_depth(x_start, y_start);
+                }
+            }
+        }
+        if !setup.blend {
+            self.farthest_depth = self
+                .block_depths
+                .iter()
+                .fold(f32::MIN, |farthest, &depth| farthest.max(depth));
+        }
+    }
+
+    /// Draws the covered pixels of row `y` from `x_start` to `x_end`, at most
+    /// one block wide, one pixel at a time, with `values` being the edge
+    /// functions at `x_start`.
+    #[cfg(not(feature = "simd"))]
+    fn fill_span(
+        &mut self,
+        setup: &TriangleSetup,
+        mut values: [i64; 3],
+        y: usize,
+        x_start: usize,
+        x_end: usize,
+        fully_covered: bool,
+    ) {
+        for x in x_start..=x_end {
+            let covered = fully_covered
+                || values
+                    .iter()
+                    .zip(&setup.edges)
+                    .all(|(&value, edge)| value - edge.bias >= 0);
+            if covered {
+                let (z, rgba) = setup.shade(values);
+                if setup.is_visible(rgba) {
+                    self.pixel(x, y, z, rgba, setup.blend);
+                }
+            }
+            for (value, edge) in values.iter_mut().zip(&setup.edges) {
+                *value += edge.step_x;
+            }
+        }
+    }
+
+    /// Draws the covered pixels of row `y` from `x_start` to `x_end`, at most
+    /// one block wide, with `values` being the edge functions at `x_start`,
+    /// testing coverage and depth and interpolating attributes for the whole
+    /// span at once.
+    #[cfg(feature = "simd")]
+    fn fill_span(
+        &mut self,
+        setup: &TriangleSetup,
+        values: [i64; 3],
+        y: usize,
+        x_start: usize,
+        x_end: usize,
+        fully_covered: bool,
+    ) {
+        let lanes = Simd::from_array(std::array::from_fn(|lane| lane as i64));
+        let values: [Simd<i64, BLOCK_SIZE>; 3] = std::array::from_fn(|i| {
+            Simd::splat(values[i]) + lanes * Simd::splat(setup.edges[i].step_x)
+        });
+
+        let length = x_end - x_start + 1;
+        let mut mask = lanes.simd_lt(Simd::splat(length as i64));
+        if !fully_covered {
+            for (value, edge) in values.iter().zip(&setup.edges) {
+                mask &= value.simd_ge(Simd::splat(edge.bias));
+            }
+        }
+        if !mask.any() {
+            return;
+        }
+
+        let weights = values.map(|value| value.cast::<f32>() / Simd::splat(setup.area));
+        let interpolate = |attributes: [f32; 3]| {
+            weights
+                .iter()
+                .zip(attributes)
+                .map(|(weight, attribute)| weight * Simd::splat(attribute))
+                .sum::<Simd<f32, BLOCK_SIZE>>()
+        };
+
+        let start = (x_start - self.x) + (y - self.y) * self.width;
+        let depth = Simd::load_or_default(&self.depth_buffer[start..start + length]);
+        let z = interpolate(setup.z_coordinates.to_array());
+        let mask = mask.cast::<i32>() & z.simd_le(depth);
+        if !mask.any() {
+            return;
+        }
+
+        let inverse_w = interpolate(setup.inverse_w.to_array());
+        let perspective_correct = |attribute: [f32; 3]| interpolate(attribute) / inverse_w;
+        let perspective_correct_vector = |attributes: [Vec3A; 3]| {
+            [0, 1, 2].map(|i| perspective_correct(attributes.map(|attribute| attribute[i])))
+        };
+        // Repeats the texture by wrapping coordinates into [0, 1), like `Vec2::fract_gl`
+        let wrap = |coordinate: Simd<f32, BLOCK_SIZE>| coordinate - coordinate.floor();
+        let [texture_x, texture_y] = [0, 1].map(|i| {
+            wrap(perspective_correct(
+                setup.textures.map(|texture| texture[i]),
+            ))
+        });
+        let normals = perspective_correct_vector(setup.normals);
+        let positions = perspective_correct_vector(setup.world_positions);
+        let colors = [0, 1, 2, 3].map(|i| perspective_correct(setup.colors.map(|color| color[i])));
+        let lane_vector = |vectors: &[Simd<f32, BLOCK_SIZE>; 3], lane: usize| {
+            Vec3A::from_array(vectors.each_ref().map(|vector| vector[lane]))
+        };
+
+        for lane in (0..length).filter(|&lane| mask.test(lane)) {
+            let uv = Vec2::new(texture_x[lane], texture_y[lane]);
+            let rgba = setup.color(
+                uv,
+                lane_vector(&positions, lane),
+                lane_vector(&normals, lane).normalize_or_zero(),
+                Vec4::from_array(colors.each_ref().map(|color| color[lane])),
+            );
+            if setup.is_visible(rgba) {
+                self.pixel(x_start + lane, y, z[lane], rgba, setup.blend);
+            }
+        }
+    }
+}
+
+/// Returns whether `triangle`, drawn with `material`, needs to be blended
+/// with what is behind it, for its material or the alpha of its vertices.
+fn is_translucent(triangle: &ScreenTriangle, material: &Material) -> bool {
+    material.is_translucent()
+        || material.alpha_cutoff.is_none()
+            && triangle
+                .vertices
+                .iter()
+                .any(|vertex| alpha_to_byte(vertex.color.w / vertex.inverse_w) < u8::MAX)
+}
+
+/// Converts an alpha between 0 and 1 into the range of a color channel.
+fn alpha_to_byte(alpha: f32) -> u8 {
+    (alpha.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Returns the depth of the nearest point of `triangle`, which is at one of
+/// its corners.
+fn nearest_depth(triangle: &ScreenTriangle) -> f32 {
+    triangle
+        .vertices
+        .iter()
+        .fold(f32::MAX, |nearest, vertex| nearest.min(vertex.position.z))
+}
+
+/// Per-triangle state needed to rasterize and shade its pixels.
+struct TriangleSetup<'a> {
+    edges: [Edge; 3],
+    /// Sum of the edge functions, which is constant over the triangle.
+    area: f32,
+    z_coordinates: Vec3A,
+    /// Reciprocals of the clip space w of the vertices, which the other
+    /// attributes are divided by.
+    inverse_w: Vec3A,
+    textures: [Vec2; 3],
+    normals: [Vec3A; 3],
+    world_positions: [Vec3A; 3],
+    colors: [Vec4; 3],
+    material: &'a Material,
+    lighting: &'a Lighting,
+    /// Position of the viewer in world space.
+    eye: Vec3A,
+    /// Whether the triangle is drawn in the translucent pass.
+    blend: bool,
+    /// Smallest alpha drawn in the opaque pass.
+    alpha_cutoff: u8,
+}
+
+impl<'a> TriangleSetup<'a> {
+    /// Returns `None` for triangles without area, which cover no pixels.
+    fn new(
+        triangle: &ScreenTriangle,
+        material: &'a Material,
+        lighting: &'a Lighting,
+        eye: Vec3A,
+        alpha_cutoff: u8,
+    ) -> Option<Self> {
+        let [a, b, c] = triangle.vertices.map(|v| {
+            (v.position.truncate() * SUBPIXEL_STEP as f32)
+                .round()
+                .as_i64vec2()
+        });
+        // Orders the vertices so that the interior is on the positive side of every edge
+        let order = if (a - b).perp_dot(c - b) < 0 {
+            [0, 2, 1]
+        } else {
+            [0, 1, 2]
+        };
+        let vertices = order.map(|i| triangle.vertices[i]);
+        let [a, b, c] = order.map(|i| [a, b, c][i]);
+        let edges = [Edge::new(b, c), Edge::new(c, a), Edge::new(a, b)];
+        let area = edges[0].at(0, 0) + edges[1].at(0, 0) + edges[2].at(0, 0);
+        if area == 0 {
+            return None;
+        }
+
+        Some(TriangleSetup {
+            edges,
+            area: area as f32,
+            z_coordinates: Vec3A::from_array(vertices.map(|v| v.position.z)),
+            inverse_w: Vec3A::from_array(vertices.map(|v| v.inverse_w)),
+            textures: vertices.map(|v| v.texture),
+            normals: vertices.map(|v| v.normal),
+            world_positions: vertices.map(|v| v.world_position),
+            colors: vertices.map(|v| v.color),
+            material,
+            lighting,
+            eye,
+            blend: is_translucent(triangle, material),
+            alpha_cutoff: material.alpha_cutoff.map_or(alpha_cutoff, alpha_to_byte),
+        })
+    }
+
+    /// Returns the diffuse and specular light reflected by the material at
+    /// texture coordinates `uv`, world space `position` and `normal`. The
+    /// diffuse light is relative to the diffuse color, and includes ambient
+    /// light scaled by the ambient color rather than replacing the diffuse
+    /// color, so that untextured materials keep their color in the shade.
+    fn light(&self, uv: Vec2, position: Vec3A, normal: Vec3A) -> (Vec3A, Vec3A) {
+        let material = self.material;
+        if material.illumination == 0 {
+            return (Vec3A::ONE, Vec3A::ZERO);
+        }
+        let mut ambient = material.ambient * self.lighting.ambient;
+        if let Some(ambient_map) = &material.ambient_map {
+            ambient *= ambient_map.sample_rgb(uv);
+        }
+
+        let mut specular = material.specular;
+        if let Some(specular_map) = &material.specular_map {
+            specular *= specular_map.sample_rgb(uv);
+        }
+        let shininess = (material.illumination >= 2 && specular != Vec3A::ZERO).then(|| {
+            let scale = material
+                .shininess_map
+                .as_ref()
+                .map_or(1.0, |shininess_map| shininess_map.sample_rgb(uv).x);
+            material.shininess * scale
+        });
+
+        let (diffuse, highlight) = self.lighting.reflect(position, normal, self.eye, shininess);
+        (ambient + diffuse, specular * highlight)
+    }
+
+    /// Returns the color of the material at texture coordinates `uv`, world
+    /// space `position` and `normal`: the diffuse texel tinted by the diffuse
+    /// and vertex colors and lit, plus specular highlights, with its alpha
+    /// scaled by the opacity and the vertex alpha.
+    fn color(&self, uv: Vec2, position: Vec3A, normal: Vec3A, vertex_color: Vec4) -> [u8; 4] {
+        let material = self.material;
+        let mut rgba = material.diffuse_map.sample(uv);
+        let (diffuse, specular) = self.light(uv, position, normal);
+        let light = material.diffuse * Vec3A::from_vec4(vertex_color) * diffuse;
+        for ((channel, light), specular) in rgba[0..3]
+            .iter_mut()
+            .zip(light.to_array())
+            .zip(specular.to_array())
+        {
+            *channel = f32::from(*channel).mul_add(light, specular * 255.0) as u8;
+        }
+        let mut dissolve = material.dissolve * vertex_color.w;
+        if let Some(dissolve_map) = &material.dissolve_map {
+            dissolve *= f32::from(dissolve_map.sample(uv)[0]) / 255.0;
+        }
+        rgba[3] = (f32::from(rgba[3]) * dissolve) as u8;
+        rgba
+    }
 
-        if let Some(slice) = frame.get_mut(4 * index..4 * index + 4) {
-            slice.copy_from_slice(&rgba);
+    /// Returns whether a pixel of this color is drawn at all: blended pixels
+    /// unless fully transparent, opaque ones if they pass the alpha test.
+    fn is_visible(&self, rgba: [u8; 4]) -> bool {
+        if self.blend {
+            rgba[3] > 0
+        } else {
+            rgba[3] >= self.alpha_cutoff
+        }
+    }
+
+    /// Returns the depth and color of the pixel with the given edge function values.
+    #[cfg(not(feature = "simd"))]
+    fn shade(&self, values: [i64; 3]) -> (f32, [u8; 4]) {
+        let weights = Vec3A::from_array(values.map(|value| value as f32)) / self.area;
+        let z = self.z_coordinates.dot(weights);
+
+        let inverse_w = self.inverse_w.dot(weights);
+
+        let apply_weights = |attributes: [Vec3A; 3]| {
+            attributes
+                .iter()
+                .zip(weights.to_array().iter())
+                .map(|(a, b)| a * b)
+                .sum::<Vec3A>()
+        };
+        let texture_coordinates = self
+            .textures
+            .iter()
+            .zip(weights.to_array().iter())
+            .map(|(a, b)| a * b)
+            .sum::<Vec2>();
+
+        let scaled_texture = (texture_coordinates / inverse_w).fract_gl();
+        let normal = (apply_weights(self.normals) / inverse_w).normalize_or_zero();
+        let position = apply_weights(self.world_positions) / inverse_w;
+        let vertex_color = self
+            .colors
+            .iter()
+            .zip(weights.to_array().iter())
+            .map(|(a, b)| a * b)
+            .sum::<Vec4>()
+            / inverse_w;
+
+        (
+            z,
+            self.color(scaled_texture, position, normal, vertex_color),
+        )
+    }
+}
+
+/// The edge function of one side of a triangle in fixed-point screen
+/// coordinates, which is positive on the side of the triangle's interior and
+/// proportional to the barycentric weight of the opposite vertex.
+#[derive(Copy, Clone, Debug)]
+struct Edge {
+    /// Change in value per pixel to the right.
+    step_x: i64,
+    /// Change in value per pixel down.
+    step_y: i64,
+    /// Value at the center of the pixel at the origin.
+    origin: i64,
+    /// Subtracted from the value before testing for coverage, so that pixels
+    /// centered exactly on a shared edge are drawn by only one triangle:
+    /// the one for which it is a top or left edge.
+    bias: i64,
+}
+
+impl Edge {
+    fn new(from: I64Vec2, to: I64Vec2) -> Self {
+        let delta = to - from;
+        let half_pixel = I64Vec2::splat(SUBPIXEL_STEP / 2);
+        let is_left = delta.y > 0;
+        let is_top = delta.y == 0 && delta.x < 0;
+        Edge {
+            step_x: delta.y * SUBPIXEL_STEP,
+            step_y: -delta.x * SUBPIXEL_STEP,
+            origin: (half_pixel - from).perp_dot(delta),
+            bias: if is_left || is_top { 0 } else { 1 },
+        }
+    }
+
+    fn at(&self, x: usize, y: usize) -> i64 {
+        self.origin + self.step_x * x as i64 + self.step_y * y as i64
+    }
+
+    /// Returns the smallest and largest biased values over the pixels of a rectangle.
+    fn range(&self, x_start: usize, x_end: usize, y_start: usize, y_end: usize) -> [i64; 2] {
+        let value = self.at(x_start, y_start) - self.bias;
+        let [dx, dy] = [
+            self.step_x * (x_end - x_start) as i64,
+            self.step_y * (y_end - y_start) as i64,
+        ];
+        [value + dx.min(0) + dy.min(0), value + dx.max(0) + dy.max(0)]
+    }
+}
+
+/// Counts of what happened to the triangles passed to
+/// [`Draw::draw_triangles`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    /// Number of triangles submitted.
+    pub triangles: usize,
+    /// Number of those skipped without being rasterized, for being behind
+    /// what was already drawn in every tile they overlap.
+    pub occluded: usize,
+}
+
+/// A rasterizer drawing screen-space triangles into an RGBA frame buffer.
+///
+/// The screen is split into tiles which are rasterized in parallel, each
+/// with its own depth buffer.
+pub struct Draw {
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+    tiles_per_row: usize,
+    // Per-triangle buffers kept between frames to avoid reallocating them
+    bounding_boxes: Vec<[usize; 4]>,
+    order: Vec<usize>,
+    translucent: Vec<usize>,
+    /// Number of tiles each triangle overlaps and was not occluded in.
+    remaining_tiles: Vec<u32>,
+    stats: DrawStats,
+    /// Materials the triangles refer to.
+    pub materials: Materials,
+    /// Opaque pixels with an alpha below this value, between 0 and 1, are
+    /// discarded, to cut out shapes such as foliage from their textures.
+    pub alpha_cutoff: f32,
+}
+
+impl Draw {
+    /// Creates a rasterizer for frames of `width` by `height` pixels.
+    pub fn new(width: usize, height: usize, materials: Materials) -> Self {
+        let mut tiles = vec![];
+        for y in (0..height).step_by(TILE_SIZE) {
+            for x in (0..width).step_by(TILE_SIZE) {
+                let tile_width = TILE_SIZE.min(width - x);
+                let tile_height = TILE_SIZE.min(height - y);
+                tiles.push(Tile::new(x, y, tile_width, tile_height));
+            }
+        }
+        Draw {
+            width,
+            height,
+            tiles,
+            tiles_per_row: width.div_ceil(TILE_SIZE),
+            bounding_boxes: vec![],
+            order: vec![],
+            translucent: vec![],
+            remaining_tiles: vec![],
+            stats: DrawStats::default(),
+            materials,
+            alpha_cutoff: DEFAULT_ALPHA_CUTOFF,
         }
-        self.depth_buffer[index] = z;
     }
 
-    // TODO: More efficient method than bounding box
     fn bounding_box(&self, vertices: &[Vec3A; 3]) -> [usize; 4] {
         vertices
             .iter()
@@ -58,80 +654,123 @@ impl Draw {
                     ]
                 },
             )
-            .map(|n| n.round() as usize)
-    }
-
-    fn triangle_area(a: Vec2, b: Vec2, c: Vec2) -> f32 {
-        // This is actually twice the triangle's area,
-        // but dividing by 2 would just cancel out later anyway
-        (c - a).perp_dot(b - a)
+            .map(|n| n as usize)
     }
 
-    pub fn fill_triangle(&mut self, frame: &mut [u8], triangle: &Triangle) {
-        let vertices = triangle.vertices.map(|v| v.position);
-        let textures = triangle.vertices.map(|v| v.texture);
-        let normals = triangle.vertices.map(|v| v.normal);
-        let texture = self
-            .textures
-            .get(triangle.texture_name.as_str())
-            .cloned()
-            .unwrap_or_default();
-
-        let [a, b, c] = vertices.map(Vec3A::truncate);
-        let z_coordinates = Vec3A::from_array(vertices.map(|point| point.z));
-        let [x_min, x_max, y_min, y_max] = self.bounding_box(&vertices);
-        let area = Self::triangle_area(a, b, c);
+    /// Rasterizes screen-space triangles into `frame`, lit by `lighting` and
+    /// seen from `eye` in world space, testing against and updating the depth
+    /// buffer.
+    ///
+    /// Opaque triangles are drawn first, in the order given. Translucent
+    /// triangles are then blended over them from back to front.
+    pub fn draw_triangles(
+        &mut self,
+        frame: &mut [u8],
+        triangles: &[ScreenTriangle],
+        lighting: &Lighting,
+        eye: Vec3A,
+    ) {
+        if self.tiles.is_empty() {
+            return;
+        }
+        for tile in &mut self.tiles {
+            tile.triangles.clear();
+            tile.occluded.clear();
+        }
 
-        for y in y_min..=y_max {
-            for x in x_min..=x_max {
-                let point = Vec2::new(x as f32, y as f32) + 0.5;
+        let mut bounding_boxes = std::mem::take(&mut self.bounding_boxes);
+        bounding_boxes.clear();
+        bounding_boxes.extend(
+            triangles
+                .iter()
+                .map(|triangle| self.bounding_box(&triangle.vertices.map(|v| v.position))),
+        );
 
-                let bcp = Self::triangle_area(b, c, point);
-                let cap = Self::triangle_area(c, a, point);
-                let abp = Self::triangle_area(a, b, point);
+        let materials = &self.materials;
+        self.order.clear();
+        self.translucent.clear();
+        for (i, triangle) in triangles.iter().enumerate() {
+            if is_translucent(triangle, &materials[triangle.material]) {
+                self.translucent.push(i);
+            } else {
+                self.order.push(i);
+            }
+        }
+        // Depth increases with distance, so the farthest triangles come first
+        let depth = |i: usize| {
+            triangles[i]
+                .vertices
+                .iter()
+                .map(|v| v.position.z)
+                .sum::<f32>()
+        };
+        self.translucent
+            .sort_by(|&a, &b| depth(b).total_cmp(&depth(a)));
+        self.order.append(&mut self.translucent);
 
-                if (bcp < 0.0) != (cap < 0.0) || (cap < 0.0) != (abp < 0.0) {
-                    continue;
+        self.remaining_tiles.clear();
+        self.remaining_tiles.resize(triangles.len(), 0);
+        for &i in &self.order {
+            let [x_min, x_max, y_min, y_max] = bounding_boxes[i];
+            for tile_y in y_min / TILE_SIZE..=y_max / TILE_SIZE {
+                for tile_x in x_min / TILE_SIZE..=x_max / TILE_SIZE {
+                    self.tiles[tile_x + tile_y * self.tiles_per_row]
+                        .triangles
+                        .push(i);
+                    self.remaining_tiles[i] += 1;
                 }
+            }
+        }
 
-                let weights = Vec3A::new(bcp, cap, abp) / area;
-                let z = z_coordinates.dot(weights);
-
-                let apply_weights = |attributes: [Vec3A; 3]| {
-                    attributes
-                        .iter()
-                        .zip(weights.to_array().iter())
-                        .map(|(a, b)| a * b)
-                        .sum::<Vec3A>()
-                };
-
-                let texture_coordinates = apply_weights(textures);
-                let normal = apply_weights(normals);
-
-                let scaled_texture = (texture_coordinates / texture_coordinates.z) % 1.0;
-                let scaled_normal = normal / normal.z;
-
-                let [texture_x, texture_y] = (scaled_texture
-                    * Vec3A::new(texture.width as f32, texture.height as f32, 1.0))
-                .truncate()
-                .to_array()
-                .map(|float| float as usize);
-
-                let mut rgba = texture.get_pixel(texture_x, texture_y);
-
-                let scaled_lighting =
-                    f32::lerp(LIGHT_MIN, LIGHT_MAX, scaled_normal.dot(LIGHT_ANGLE));
-
-                for channel in &mut rgba[0..3] {
-                    *channel = (f32::from(*channel) * scaled_lighting) as u8;
+        let width = self.width;
+        let alpha_cutoff = alpha_to_byte(self.alpha_cutoff);
+        self.tiles
+            .par_iter_mut()
+            .filter(|tile| !tile.triangles.is_empty())
+            .for_each(|tile| {
+                tile.load(frame, width);
+                for index in 0..tile.triangles.len() {
+                    let i = tile.triangles[index];
+                    if tile.is_occluded(&triangles[i], bounding_boxes[i]) {
+                        tile.occluded.push(i);
+                        continue;
+                    }
+                    tile.fill_triangle(
+                        &triangles[i],
+                        bounding_boxes[i],
+                        &materials[triangles[i].material],
+                        lighting,
+                        eye,
+                        alpha_cutoff,
+                    );
                 }
+            });
 
-                self.pixel(frame, x, y, z, rgba);
+        self.stats = DrawStats {
+            triangles: triangles.len(),
+            occluded: 0,
+        };
+        for tile in self.tiles.iter().filter(|tile| !tile.triangles.is_empty()) {
+            tile.store(frame, width);
+            for &i in &tile.occluded {
+                self.remaining_tiles[i] -= 1;
+                if self.remaining_tiles[i] == 0 {
+                    self.stats.occluded += 1;
+                }
             }
         }
+        self.bounding_boxes = bounding_boxes;
+    }
+
+    /// Returns statistics about the last call to [`Draw::draw_triangles`].
+    pub fn stats(&self) -> DrawStats {
+        self.stats
     }
 
+    /// Resets the depth buffer, to be called between frames.
     pub fn clear_depth_buffer(&mut self) {
-        self.depth_buffer.fill(0.0);
+        for tile in &mut self.tiles {
+            tile.clear_depth(0.0);
+        }
     }
 }
@@ -1,112 +1,156 @@
-use glam::{Mat4, Vec2, Vec3A, Vec4};
+//! Triangles and vertices as they move through the pipeline.
 
-#[derive(Copy, Clone, Default, Debug)]
+use crate::material::MaterialId;
+use glam::{Mat3A, Mat4, Vec2, Vec3A, Vec4};
+
+/// A corner of a triangle in a [`Mesh`](crate::Mesh).
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vertex {
+    /// Position in model space.
     pub position: Vec3A,
+    /// Surface normal.
     pub normal: Vec3A,
+    /// Texture coordinates, with the third component set to one.
     pub texture: Vec3A,
+    /// Color multiplied with the material's, as RGBA from 0 to 1.
+    pub color: Vec4,
 }
 
-#[derive(Debug)]
-pub struct Triangle {
-    pub vertices: [Vertex; 3],
-    pub normal: Vec3A,
-    pub texture_name: String,
-    pub centroid: Vec3A,
-}
-
-impl Triangle {
-    pub fn new([a, b, c]: [Vertex; 3], texture_name: &(impl ToString + ?Sized)) -> Self {
+impl Default for Vertex {
+    fn default() -> Self {
         Self {
-            vertices: [a, b, c],
-            normal: (b.position - c.position)
-                .cross(c.position - a.position)
-                .normalize(),
-            texture_name: texture_name.to_string(),
-            centroid: (a.position + b.position + c.position) / 3.0,
+            position: Vec3A::ZERO,
+            normal: Vec3A::ZERO,
+            texture: Vec3A::ZERO,
+            color: Vec4::ONE,
         }
     }
+}
 
-    pub fn project(&self, view_projection_matrix: Mat4) -> ProjectedTriangle {
-        ProjectedTriangle {
-            vertices: self.vertices.map(|vertex| ProjectedVertex {
-                position: view_projection_matrix * vertex.position.extend(1.0),
-                normal: vertex.normal,
-                texture: vertex.texture,
-            }),
-            normal: self.normal,
-            texture_name: self.texture_name.clone(),
-            centroid: self.centroid,
+impl Vertex {
+    /// Transforms the vertex into world space by `model_matrix` and from
+    /// there into clip space, with its normal transformed by `normal_matrix`,
+    /// as returned by [`normal_matrix`].
+    pub fn project(
+        &self,
+        model_matrix: Mat4,
+        normal_matrix: Mat3A,
+        view_projection_matrix: Mat4,
+    ) -> ProjectedVertex {
+        let world_position = model_matrix.transform_point3a(self.position);
+        ProjectedVertex {
+            position: view_projection_matrix * world_position.extend(1.0),
+            normal: (normal_matrix * self.normal).normalize_or_zero(),
+            texture: self.texture,
+            world_position,
+            color: self.color,
         }
     }
+}
 
-    pub fn is_facing_viewer(&self, viewer_position: Vec3A) -> bool {
-        self.normal
-            .dot(viewer_position - self.centroid)
-            .is_sign_negative()
-    }
+/// Returns the matrix transforming normals along with positions transformed
+/// by `model_matrix`: the inverse transpose of its upper 3x3 part, which keeps
+/// normals perpendicular to their surfaces under non-uniform scaling.
+pub fn normal_matrix(model_matrix: Mat4) -> Mat3A {
+    Mat3A::from_mat4(model_matrix).inverse().transpose()
 }
 
+/// A corner of a [`ProjectedTriangle`].
 #[derive(Debug, Copy, Clone, Default)]
 pub struct ProjectedVertex {
+    /// Position in homogeneous clip space.
     pub position: Vec4,
+    /// Surface normal in world space.
     pub normal: Vec3A,
+    /// Texture coordinates, with the third component set to one.
     pub texture: Vec3A,
+    /// Position in world space.
+    pub world_position: Vec3A,
+    /// Color multiplied with the material's, as RGBA from 0 to 1.
+    pub color: Vec4,
 }
 
 impl ProjectedVertex {
+    /// Linearly interpolates every attribute between `self` and `rhs`.
     pub fn lerp(&self, rhs: Self, s: f32) -> Self {
         Self {
             position: self.position.lerp(rhs.position, s),
             normal: self.normal.lerp(rhs.normal, s),
             texture: self.texture.lerp(rhs.texture, s),
+            world_position: self.world_position.lerp(rhs.world_position, s),
+            color: self.color.lerp(rhs.color, s),
         }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// A triangle in clip space, ready to be clipped.
+#[derive(Debug, Clone, Copy, Default)]
 pub struct ProjectedTriangle {
+    /// The corners of the triangle.
     pub vertices: [ProjectedVertex; 3],
-    pub normal: Vec3A,
-    pub texture_name: String,
-    pub centroid: Vec3A,
+    /// Material the triangle is drawn with.
+    pub material: MaterialId,
 }
 
-impl From<&Triangle> for ProjectedTriangle {
-    fn from(triangle: &Triangle) -> Self {
-        ProjectedTriangle {
-            vertices: triangle.vertices.map(|v| ProjectedVertex {
-                position: v.position.extend(1.0),
-                normal: v.normal,
-                texture: v.texture,
-            }),
-            normal: triangle.normal,
-            texture_name: triangle.texture_name.clone(),
-            centroid: triangle.centroid,
-        }
+impl ProjectedTriangle {
+    /// Returns whether the front side of the triangle can be seen from
+    /// `viewer_position` in world space.
+    pub fn is_facing_viewer(&self, viewer_position: Vec3A) -> bool {
+        let [a, b, c] = self.vertices.map(|vertex| vertex.world_position);
+        let normal = (b - c).cross(c - a);
+        let centroid = (a + b + c) / 3.0;
+        normal.dot(viewer_position - centroid).is_sign_negative()
     }
-}
 
-impl ProjectedTriangle {
-    pub fn divide_and_scale(&self, size: Vec2) -> Triangle {
-        Triangle {
+    /// Performs the perspective divide and maps the result onto a screen of
+    /// `size` pixels, ready for [`Draw::draw_triangles`](crate::Draw::draw_triangles).
+    pub fn divide_and_scale(&self, size: Vec2) -> ScreenTriangle {
+        ScreenTriangle {
             vertices: self.vertices.map(|vertex| {
-                let perspective_divided = Vec3A::from_vec4(vertex.position / vertex.position.w);
+                let inverse_w = vertex.position.w.recip();
+                let perspective_divided = Vec3A::from_vec4(vertex.position * inverse_w);
                 let flipped = perspective_divided.with_y(-perspective_divided.y);
                 let centered = flipped + Vec3A::new(1.0, 1.0, 0.0);
-                let position = centered * Vec3A::from((0.5 * size).extend(1.0));
-                let texture =
-                    Vec3A::new(vertex.texture.x, vertex.texture.y, 1.0) / vertex.position.w;
-                let normal = Vec3A::new(vertex.normal.x, vertex.normal.y, 1.0) / vertex.position.w;
-                Vertex {
-                    position,
-                    normal,
-                    texture,
+                ScreenVertex {
+                    position: centered * Vec3A::from((0.5 * size).extend(1.0)),
+                    inverse_w,
+                    normal: vertex.normal * inverse_w,
+                    texture: vertex.texture.truncate() * inverse_w,
+                    world_position: vertex.world_position * inverse_w,
+                    color: vertex.color * inverse_w,
                 }
             }),
-            normal: self.normal,
-            texture_name: self.texture_name.clone(),
-            centroid: self.centroid,
+            material: self.material,
         }
     }
 }
+
+/// A corner of a [`ScreenTriangle`].
+///
+/// Attributes other than the position are divided by the clip space w, so
+/// that they can be interpolated linearly in screen space and divided by the
+/// interpolated `inverse_w` to correct for perspective.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ScreenVertex {
+    /// Position in pixels, with depth in z.
+    pub position: Vec3A,
+    /// Reciprocal of the clip space w.
+    pub inverse_w: f32,
+    /// Surface normal in world space, divided by w.
+    pub normal: Vec3A,
+    /// Texture coordinates, divided by w.
+    pub texture: Vec2,
+    /// Position in world space, divided by w.
+    pub world_position: Vec3A,
+    /// Color multiplied with the material's, divided by w.
+    pub color: Vec4,
+}
+
+/// A triangle in screen space, ready to be rasterized.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScreenTriangle {
+    /// The corners of the triangle.
+    pub vertices: [ScreenVertex; 3],
+    /// Material the triangle is drawn with.
+    pub material: MaterialId,
+}
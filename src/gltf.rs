@@ -0,0 +1,315 @@
+//! Loading models from glTF 2.0 files.
+
+use crate::{
+    geometry::Vertex,
+    material::{Material, MaterialId, Materials, Texture},
+    mesh::{Mesh, ObjectData},
+};
+use ::gltf::{
+    accessor::{DataType, Dimensions},
+    buffer,
+    image::Format,
+    material::AlphaMode,
+    mesh::Mode,
+    Node, Primitive,
+};
+use anyhow::{bail, Context, Result};
+use glam::{Mat3A, Mat4, Vec3A, Vec4};
+use image::{DynamicImage, ImageBuffer, Pixel};
+use std::{panic, path::Path};
+
+/// Loads a model from a glTF file, either JSON (`.gltf`) referring to
+/// separate or embedded buffers and images, or binary (`.glb`).
+///
+/// The meshes of the default scene, or else of the first one, are placed by
+/// the transforms of their nodes and the nodes' ancestors, and grouped under
+/// the name of their node, or else of the mesh. Only the base color factor
+/// and texture and the alpha mode of each material are used, with materials
+/// named after their index, as in `#0`, unless they have a name of their own.
+pub fn load_gltf_file(path: impl AsRef<Path>) -> Result<ObjectData> {
+    let path = path.as_ref();
+    // The gltf crate indexes into the document while validating it and reading
+    // its buffers, and panics on indices out of range rather than failing
+    panic::catch_unwind(|| load(path)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown error");
+        bail!(
+            "In \"{}\": The file is malformed: {message}",
+            path.display()
+        )
+    })
+}
+
+/// Loads a model from a glTF file as described in [`load_gltf_file`], which
+/// turns panics into errors.
+fn load(path: &Path) -> Result<ObjectData> {
+    let err = |message: &str| format!("In \"{}\": {message}", path.display());
+
+    let (document, buffers, images) =
+        ::gltf::import(path).with_context(|| format!("Failed to load \"{}\"", path.display()))?;
+
+    let mut materials = Materials::new();
+    let mut material_ids = Vec::with_capacity(document.materials().len());
+    for (index, material) in document.materials().enumerate() {
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor();
+        let opaque = material.alpha_mode() == AlphaMode::Opaque;
+        let mut properties = Material::from_color(Vec3A::new(r, g, b));
+        if !opaque {
+            properties.dissolve = a;
+        }
+        if material.alpha_mode() == AlphaMode::Mask {
+            // The specification's default cutoff
+            properties.alpha_cutoff = Some(material.alpha_cutoff().unwrap_or(0.5));
+        }
+        if let Some(info) = pbr.base_color_texture() {
+            if info.tex_coord() != 0 {
+                bail!(err(&format!(
+                    "Material {index} uses texture coordinates {}, but only set 0 is supported",
+                    info.tex_coord()
+                )));
+            }
+            let image = info.texture().source().index();
+            let image = decode_image(&images[image])
+                .with_context(|| err(&format!("Image {image} has fewer pixels than its size")))?;
+            // Opaque materials ignore the alpha channel entirely
+            let image = if opaque {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            } else {
+                image
+            };
+            properties.diffuse_map = Texture::from_image(&image);
+        }
+
+        let name = material
+            .name()
+            .filter(|name| materials.id(name).is_none())
+            .map_or_else(|| format!("#{index}"), str::to_string);
+        material_ids.push(materials.insert(&name, properties));
+    }
+
+    let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    else {
+        bail!(err("There is no scene to load"));
+    };
+    let mut loader = Loader {
+        buffers: &buffers,
+        material_ids: &material_ids,
+        triangles: vec![],
+        group_names: vec![],
+    };
+    for node in scene.nodes() {
+        loader
+            .add_node(&node, Mat4::IDENTITY)
+            .with_context(|| err(&format!("Failed to load node {}", node.index())))?;
+    }
+
+    let Loader {
+        triangles,
+        group_names,
+        ..
+    } = loader;
+    Ok(ObjectData {
+        mesh: Mesh::from_grouped_triangles(triangles, &group_names),
+        materials,
+    })
+}
+
+/// Triangles gathered from the nodes of a glTF scene.
+struct Loader<'a> {
+    buffers: &'a [buffer::Data],
+    /// Handle of each of the file's materials, by index.
+    material_ids: &'a [MaterialId],
+    triangles: Vec<([Vertex; 3], MaterialId, Option<usize>)>,
+    group_names: Vec<String>,
+}
+
+impl Loader<'_> {
+    /// Adds the triangles of `node` and its descendants, transformed into
+    /// the space of the scene, with `parent` being the transform of the
+    /// node's parent.
+    fn add_node(&mut self, node: &Node, parent: Mat4) -> Result<()> {
+        let matrix = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            let group = node.name().or(mesh.name()).map(|name| {
+                self.group_names
+                    .iter()
+                    .position(|group| group == name)
+                    .unwrap_or_else(|| {
+                        self.group_names.push(name.to_string());
+                        self.group_names.len() - 1
+                    })
+            });
+            for primitive in mesh.primitives() {
+                self.add_primitive(&primitive, matrix, group)
+                    .with_context(|| {
+                        format!(
+                            "Failed to load primitive {} of mesh {}",
+                            primitive.index(),
+                            mesh.index()
+                        )
+                    })?;
+            }
+        }
+        for child in node.children() {
+            self.add_node(&child, matrix)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the triangles of `primitive`, transformed by `matrix`. Points
+    /// and lines are skipped, as there is nothing to fill.
+    fn add_primitive(
+        &mut self,
+        primitive: &Primitive,
+        matrix: Mat4,
+        group: Option<usize>,
+    ) -> Result<()> {
+        let mode = primitive.mode();
+        if !matches!(
+            mode,
+            Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+        ) {
+            return Ok(());
+        }
+
+        // The reader panics on indices of any other type
+        if let Some(accessor) = primitive.indices() {
+            if accessor.dimensions() != Dimensions::Scalar
+                || !matches!(
+                    accessor.data_type(),
+                    DataType::U8 | DataType::U16 | DataType::U32
+                )
+            {
+                bail!("The indices are not unsigned integers");
+            }
+        }
+
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let positions: Vec<Vec3A> = reader
+            .read_positions()
+            .context("The primitive has no positions")?
+            .map(|position| matrix.transform_point3a(Vec3A::from_array(position)))
+            .collect();
+        let normal_matrix = Mat3A::from_mat4(matrix).inverse().transpose();
+        let normals: Option<Vec<Vec3A>> = reader.read_normals().map(|normals| {
+            normals
+                .map(|normal| (normal_matrix * Vec3A::from_array(normal)).normalize_or_zero())
+                .collect()
+        });
+        // The image is flipped when loaded, so the coordinates are too
+        let textures: Option<Vec<Vec3A>> = reader.read_tex_coords(0).map(|textures| {
+            textures
+                .into_f32()
+                .map(|[u, v]| Vec3A::new(u, 1.0 - v, 1.0))
+                .collect()
+        });
+        let lengths =
+            [normals.as_ref(), textures.as_ref()].map(|attribute| attribute.map(Vec::len));
+        if lengths
+            .iter()
+            .flatten()
+            .any(|&length| length != positions.len())
+        {
+            bail!("The primitive's attributes have different numbers of vertices");
+        }
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if let Some(&index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+            bail!(
+                "The index {index} is out of range, with {} vertices",
+                positions.len()
+            );
+        }
+
+        let corners: Vec<[u32; 3]> = match mode {
+            Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+                .map(|i| {
+                    // Every other triangle of a strip is wound the other way
+                    if i % 2 == 0 {
+                        [indices[i], indices[i + 1], indices[i + 2]]
+                    } else {
+                        [indices[i + 1], indices[i], indices[i + 2]]
+                    }
+                })
+                .collect(),
+            Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+                .map(|i| [indices[0], indices[i], indices[i + 1]])
+                .collect(),
+            _ => indices
+                .chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+                .collect(),
+        };
+
+        let material = primitive
+            .material()
+            .index()
+            .map_or(MaterialId::DEFAULT, |index| self.material_ids[index]);
+        // Mirroring transforms turn the triangles inside out
+        let mirrored = matrix.determinant() < 0.0;
+        for mut triangle in corners {
+            if mirrored {
+                triangle.swap(1, 2);
+            }
+            let [a, b, c] = triangle.map(|i| positions[i as usize]);
+            let flat_normal = (b - a).cross(c - a).normalize_or(Vec3A::Y);
+            let vertices = triangle.map(|i| {
+                let i = i as usize;
+                Vertex {
+                    position: positions[i],
+                    normal: normals.as_ref().map_or(flat_normal, |normals| normals[i]),
+                    texture: textures
+                        .as_ref()
+                        .map_or(Vec3A::ZERO, |textures| textures[i]),
+                    color: Vec4::ONE,
+                }
+            });
+            self.triangles.push((vertices, material, group));
+        }
+        Ok(())
+    }
+}
+
+/// Converts an image decoded by the glTF importer back into a
+/// [`DynamicImage`], returning `None` if its size does not match its pixels.
+fn decode_image(data: &::gltf::image::Data) -> Option<DynamicImage> {
+    fn buffer<P: Pixel, T>(
+        data: &::gltf::image::Data,
+        from_bytes: impl Fn(&[u8]) -> T,
+    ) -> Option<ImageBuffer<P, Vec<P::Subpixel>>>
+    where
+        P::Subpixel: From<T>,
+    {
+        let size = size_of::<P::Subpixel>();
+        let subpixels = data
+            .pixels
+            .chunks_exact(size)
+            .map(|bytes| from_bytes(bytes).into())
+            .collect();
+        ImageBuffer::from_raw(data.width, data.height, subpixels)
+    }
+
+    let u8 = |bytes: &[u8]| bytes[0];
+    let u16 = |bytes: &[u8]| u16::from_ne_bytes([bytes[0], bytes[1]]);
+    let f32 = |bytes: &[u8]| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Some(match data.format {
+        Format::R8 => DynamicImage::ImageLuma8(buffer(data, u8)?),
+        Format::R8G8 => DynamicImage::ImageLumaA8(buffer(data, u8)?),
+        Format::R8G8B8 => DynamicImage::ImageRgb8(buffer(data, u8)?),
+        Format::R8G8B8A8 => DynamicImage::ImageRgba8(buffer(data, u8)?),
+        Format::R16 => DynamicImage::ImageLuma16(buffer(data, u16)?),
+        Format::R16G16 => DynamicImage::ImageLumaA16(buffer(data, u16)?),
+        Format::R16G16B16 => DynamicImage::ImageRgb16(buffer(data, u16)?),
+        Format::R16G16B16A16 => DynamicImage::ImageRgba16(buffer(data, u16)?),
+        Format::R32G32B32FLOAT => DynamicImage::ImageRgb32F(buffer(data, f32)?),
+        Format::R32G32B32A32FLOAT => DynamicImage::ImageRgba32F(buffer(data, f32)?),
+    })
+}
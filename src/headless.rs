@@ -0,0 +1,62 @@
+//! Offscreen rendering into images, for use without a display.
+
+use crate::{
+    camera::Camera,
+    draw::Draw,
+    light::Lighting,
+    pipeline::Pipeline,
+    scene::{RenderSettings, Scene},
+};
+use anyhow::{Context, Result};
+use glam::UVec2;
+use image::RgbaImage;
+use std::path::Path;
+
+/// Renders `scene` from the point of view of `camera`, lit by `lighting` and
+/// configured by `settings`, into an offscreen image of `size` pixels,
+/// without opening a window.
+pub fn render_to_image(
+    scene: &Scene,
+    camera: &Camera,
+    lighting: &Lighting,
+    settings: &RenderSettings,
+    size: UVec2,
+    clear_color: [u8; 4],
+) -> RgbaImage {
+    let mut camera = *camera;
+    camera.aspect_ratio = size.x as f32 / size.y as f32;
+
+    let mut pipeline = Pipeline::new();
+    let mut draw = Draw::new(size.x as usize, size.y as usize, scene.materials.clone());
+    settings.apply(&mut pipeline, &mut draw);
+    draw.clear_depth_buffer();
+
+    let mut frame = clear_color.repeat((size.x * size.y) as usize);
+    pipeline.render(
+        &mut frame,
+        &mut draw,
+        scene,
+        &camera,
+        lighting,
+        size.as_vec2(),
+    );
+
+    RgbaImage::from_raw(size.x, size.y, frame).expect("Frame should match the image dimensions")
+}
+
+/// Renders `scene` like [`render_to_image`] and saves the result to `path`.
+/// The image format is chosen from the file extension.
+pub fn render_to_file(
+    scene: &Scene,
+    camera: &Camera,
+    lighting: &Lighting,
+    settings: &RenderSettings,
+    size: UVec2,
+    clear_color: [u8; 4],
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let path = path.as_ref();
+    render_to_image(scene, camera, lighting, settings, size, clear_color)
+        .save(path)
+        .with_context(|| format!("Failed to save render to \"{}\"", path.display()))
+}
@@ -0,0 +1,46 @@
+//! A small software rasterizer for textured triangle meshes.
+//!
+//! Models are loaded with [`load_model_file`] into an indexed [`Mesh`],
+//! placed any number of times in a [`Scene`] by its transformed nodes,
+//! projected through a [`Camera`], clipped with [`clip`] and rasterized into
+//! an RGBA frame buffer by [`Draw`]. A [`Pipeline`] chains these steps
+//! together, reusing its buffers from frame to frame, and [`headless`]
+//! renders straight to an image without opening a window.
+
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+#![warn(missing_docs)]
+
+pub mod bvh;
+pub mod camera;
+pub mod draw;
+pub mod geometry;
+pub mod gltf;
+pub mod headless;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod pipeline;
+pub mod ply;
+pub mod scene;
+pub mod stl;
+
+pub use crate::{
+    bvh::{Aabb, Bvh, Frustum},
+    camera::{Camera, Movement},
+    draw::{Draw, DrawStats},
+    geometry::{ProjectedTriangle, ProjectedVertex, ScreenTriangle, ScreenVertex, Vertex},
+    gltf::load_gltf_file,
+    light::{Light, Lighting},
+    material::{load_mtl_file, Material, MaterialId, Materials, Texture},
+    mesh::{
+        load_from_obj_file, load_from_obj_file_with_options, load_model_file, Group, Mesh,
+        ObjOptions, ObjectData, Submesh,
+    },
+    pipeline::{clip, Pipeline, PipelineStats},
+    ply::load_ply_file,
+    scene::{
+        load_scene_file, CameraPose, MeshId, Node, NodeId, RenderSettings, Scene, SceneData,
+        Transform,
+    },
+    stl::load_stl_file,
+};
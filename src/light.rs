@@ -0,0 +1,241 @@
+//! Light sources and the light they cast onto surfaces.
+
+use glam::Vec3A;
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// How the intensity of a point or spot light falls off with distance `d`,
+/// as `1 / (constant + linear * d + quadratic * d²)`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Attenuation {
+    /// Falloff independent of distance.
+    pub constant: f32,
+    /// Falloff proportional to distance.
+    pub linear: f32,
+    /// Falloff proportional to the square of the distance.
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    /// No falloff, so the light reaches every distance at full intensity.
+    pub const NONE: Self = Self {
+        constant: 1.0,
+        linear: 0.0,
+        quadratic: 0.0,
+    };
+
+    /// Physically based falloff with the inverse square of the distance.
+    pub const INVERSE_SQUARE: Self = Self {
+        constant: 0.0,
+        linear: 0.0,
+        quadratic: 1.0,
+    };
+
+    /// Returns the factor the intensity is scaled by at `distance`.
+    pub fn at(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + distance * (self.linear + distance * self.quadratic))
+            .max(f32::EPSILON)
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self::INVERSE_SQUARE
+    }
+}
+
+/// Where light comes from and the shape of the area it illuminates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// Parallel rays from infinitely far away, like sunlight.
+    Directional {
+        /// Direction the light travels in.
+        direction: Vec3A,
+    },
+    /// Light radiating equally in every direction from a point.
+    Point {
+        /// Position in world space.
+        position: Vec3A,
+        /// Falloff with distance.
+        attenuation: Attenuation,
+    },
+    /// Light radiating from a point in a cone, like a flashlight.
+    Spot {
+        /// Position in world space.
+        position: Vec3A,
+        /// Direction the cone points in.
+        direction: Vec3A,
+        /// Falloff with distance.
+        attenuation: Attenuation,
+        /// Angle in radians from the center of the cone within which the light
+        /// is at full intensity.
+        inner_angle: f32,
+        /// Angle in radians from the center of the cone beyond which there is
+        /// no light. Between the two angles, the light fades out smoothly.
+        outer_angle: f32,
+    },
+}
+
+/// A source of light.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    /// Where the light comes from.
+    pub kind: LightKind,
+    /// Color of the light, usually with components between 0 and 1.
+    pub color: Vec3A,
+    /// Brightness, scaling the color.
+    pub intensity: f32,
+}
+
+impl Light {
+    /// Creates a white directional light shining in `direction`.
+    pub fn directional(direction: Vec3A, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional {
+                direction: direction.normalize(),
+            },
+            color: Vec3A::ONE,
+            intensity,
+        }
+    }
+
+    /// Creates a white point light at `position` with inverse square falloff.
+    pub fn point(position: Vec3A, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Point {
+                position,
+                attenuation: Attenuation::default(),
+            },
+            color: Vec3A::ONE,
+            intensity,
+        }
+    }
+
+    /// Creates a white spot light at `position` shining in `direction`, with
+    /// inverse square falloff and a cone between `inner_angle` and
+    /// `outer_angle` radians.
+    pub fn spot(
+        position: Vec3A,
+        direction: Vec3A,
+        inner_angle: f32,
+        outer_angle: f32,
+        intensity: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                position,
+                direction: direction.normalize(),
+                attenuation: Attenuation::default(),
+                inner_angle,
+                outer_angle,
+            },
+            color: Vec3A::ONE,
+            intensity,
+        }
+    }
+
+    /// Sets the color of the light.
+    pub fn with_color(self, color: Vec3A) -> Self {
+        Self { color, ..self }
+    }
+
+    /// Returns the unit direction from `position` towards the light, and the
+    /// color and intensity of the light arriving there, or `None` if no light
+    /// arrives.
+    pub fn incident(&self, position: Vec3A) -> Option<(Vec3A, Vec3A)> {
+        let radiance = self.color * self.intensity;
+        match self.kind {
+            LightKind::Directional { direction } => Some((-direction, radiance)),
+            LightKind::Point {
+                position: light_position,
+                attenuation,
+            } => {
+                let offset = light_position - position;
+                let distance = offset.length();
+                let to_light = offset.try_normalize()?;
+                Some((to_light, radiance * attenuation.at(distance)))
+            }
+            LightKind::Spot {
+                position: light_position,
+                direction,
+                attenuation,
+                inner_angle,
+                outer_angle,
+            } => {
+                let offset = light_position - position;
+                let distance = offset.length();
+                let to_light = offset.try_normalize()?;
+                let cone = smoothstep(
+                    outer_angle.cos(),
+                    inner_angle.cos(),
+                    (-to_light).dot(direction),
+                );
+                (cone > 0.0).then(|| (to_light, radiance * attenuation.at(distance) * cone))
+            }
+        }
+    }
+}
+
+/// Smoothly interpolates from 0 at `edge0` to 1 at `edge1`.
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge0 >= edge1 {
+        return f32::from(x >= edge1);
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Every light illuminating a scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lighting {
+    /// Light reaching every surface equally from all directions.
+    pub ambient: Vec3A,
+    /// Light sources.
+    pub lights: Vec<Light>,
+}
+
+impl Lighting {
+    /// Returns the diffuse and specular light reflected from a surface at
+    /// `position` with unit `normal` towards a viewer at `eye`, not including
+    /// ambient light. Specular highlights follow the Blinn-Phong model with
+    /// exponent `shininess`, and are skipped if it is `None`.
+    pub fn reflect(
+        &self,
+        position: Vec3A,
+        normal: Vec3A,
+        eye: Vec3A,
+        shininess: Option<f32>,
+    ) -> (Vec3A, Vec3A) {
+        let to_eye = (eye - position).normalize_or_zero();
+        let mut diffuse = Vec3A::ZERO;
+        let mut specular = Vec3A::ZERO;
+        for (to_light, radiance) in self
+            .lights
+            .iter()
+            .filter_map(|light| light.incident(position))
+        {
+            let normal_dot_light = normal.dot(to_light);
+            if normal_dot_light <= 0.0 {
+                continue;
+            }
+            diffuse += radiance * normal_dot_light;
+            if let Some(shininess) = shininess {
+                let halfway = (to_light + to_eye).normalize_or_zero();
+                specular += radiance * normal.dot(halfway).max(0.0).powf(shininess);
+            }
+        }
+        (diffuse, specular)
+    }
+}
+
+impl Default for Lighting {
+    /// Dim white ambient light and a single sun shining down at an angle.
+    fn default() -> Self {
+        Self {
+            ambient: Vec3A::splat(0.75),
+            lights: vec![Light::directional(
+                Vec3A::new(-FRAC_1_SQRT_2, -FRAC_1_SQRT_2, 0.0),
+                0.25,
+            )],
+        }
+    }
+}
@@ -1,15 +1,18 @@
-#![feature(iter_partition_in_place)]
+mod cli;
 
-mod camera;
-mod draw;
-mod geometry;
-mod mesh;
-
-use crate::geometry::{ProjectedTriangle, ProjectedVertex};
-use crate::{camera::Camera, draw::Draw, geometry::Triangle, mesh::ObjectData};
-use glam::{Vec2, Vec3A, Vec4};
+use crate::cli::Args;
+use clap::Parser;
+use drawing::{
+    headless, Camera, CameraPose, Draw, Lighting, Movement, Pipeline, RenderSettings, Scene,
+    SceneData,
+};
+use glam::{UVec2, Vec2, Vec3A};
 use pixels::{Pixels, SurfaceTexture};
-use std::{sync::Arc, time::Duration, f32::consts::PI};
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    sync::Arc,
+    time::Duration,
+};
 use win_loop::{
     anyhow::Result,
     start,
@@ -23,122 +26,77 @@ use win_loop::{
     App, Context, InputState,
 };
 
-const OBJECT_PATH: &str = "assets/castle/castle.obj";
-const CLEAR_COLOR: [u8; 4] = [110, 177, 255, 255];
-const WIDTH: u32 = 800;
-const HEIGHT: u32 = 600;
-const SCALE: u32 = 8;
 const TARGET_FRAME_TIME_SECONDS: f32 = 1.0 / 144.0;
 const MAX_FRAME_TIME_SECONDS: f32 = 0.1;
-const CAMERA_POSITION: Vec3A = Vec3A::new(0.0, 3.0, -4.0);
-const CAMERA_ROTATION: Vec2 = Vec2::new(0.0, PI);
-const FULLSCREEN: bool = true;
-
-const CLIPPING_PLANES: [Vec4; 5] = [
-    Vec4::new(0.0, 0.0, 1.0, 1.0),  // Near
-    Vec4::new(1.0, 0.0, 0.0, 1.0),  // Left
-    Vec4::new(-1.0, 0.0, 0.0, 1.0), // Right
-    Vec4::new(0.0, -1.0, 0.0, 1.0), // Top
-    Vec4::new(0.0, 1.0, 0.0, 1.0),  // Bottom
-];
-
-fn intersection(plane: Vec4, a: ProjectedVertex, b: ProjectedVertex) -> ProjectedVertex {
-    let s = plane.dot(a.position) / (plane.dot(a.position) - plane.dot(b.position));
-    a.lerp(b, s)
-}
-
-fn clip(triangles: &mut Vec<ProjectedTriangle>) {
-    for plane in CLIPPING_PLANES {
-        let mut i = 0;
-        let mut length = triangles.len();
-
-        while i < length {
-            let mut triangle = triangles[i].clone();
-            let inside = triangle
-                .vertices
-                .iter_mut()
-                .partition_in_place(|point| point.position.dot(plane).is_sign_positive());
-            match inside {
-                1 | 2 => {
-                    let [a, b, c] = [4, 5, 6].map(|j| triangle.vertices[(j - inside) % 3]);
-                    let [ab, ac] = [b, c].map(|point| intersection(plane, a, point));
-
-                    if inside == 1 {
-                        triangles[i].vertices = [ac, a, ab];
-                    } else {
-                        triangles[i].vertices = [ac, b, ab];
-                        triangle.vertices = [ac, b, c];
-                        triangles.insert(i, triangle);
-                        length += 1;
-                    }
-                    i += 1;
-                }
-                3 => i += 1,
-                _ => {
-                    triangles.swap_remove(i);
-                    length -= 1;
-                }
-            }
-        }
-    }
-}
+const DEFAULT_POSE: CameraPose = CameraPose {
+    position: Vec3A::new(0.0, 3.0, -4.0),
+    rotation: Vec2::new(0.0, PI),
+    fov: FRAC_PI_2,
+};
+const DEFAULT_SCALE: u32 = 8;
+const DEFAULT_CLEAR_COLOR: [u8; 4] = [110, 177, 255, 255];
 
 struct Application {
-    mesh: Vec<Triangle>,
+    scene: Scene,
     pixels: Pixels,
     scale: u32,
     camera: Camera,
+    lighting: Lighting,
     draw: Draw,
+    pipeline: Pipeline,
+    settings: RenderSettings,
     size: Vec2,
+    clear_color: [u8; 4],
 }
 
 impl Application {
     fn clear_screen(&mut self) {
         for pixels in self.pixels.frame_mut().chunks_exact_mut(4) {
-            pixels.copy_from_slice(&CLEAR_COLOR);
+            pixels.copy_from_slice(&self.clear_color);
         }
     }
 }
 
+fn movement(key_code: KeyCode) -> Option<Movement> {
+    match key_code {
+        KeyCode::KeyW => Some(Movement::Forward),
+        KeyCode::KeyS => Some(Movement::Backward),
+        KeyCode::KeyA => Some(Movement::Left),
+        KeyCode::KeyD => Some(Movement::Right),
+        KeyCode::Space => Some(Movement::Up),
+        KeyCode::ShiftLeft => Some(Movement::Down),
+        _ => None,
+    }
+}
+
 impl App for Application {
     fn update(&mut self, ctx: &mut Context) -> Result<()> {
-        // Keeps the mesh sorted so that closer triangles are drawn first, resulting in fewer draw calls.
-        let position = self.camera.position;
-        self.mesh
-            .sort_unstable_by_key(|triangle| position.distance(triangle.centroid) as i32);
-
         if ctx.input.is_logical_key_pressed(NamedKey::Escape) {
             ctx.exit();
         }
 
-        let keys: Vec<KeyCode> = ctx
+        let movements: Vec<Movement> = ctx
             .input
             .physical_keys()
             .iter()
             .filter(|(_, input_state)| matches!(input_state, InputState::Down))
-            .map(|(&key_code, _)| key_code)
+            .filter_map(|(&key_code, _)| movement(key_code))
             .collect();
-        self.camera.update(&keys);
+        self.camera.update(&movements);
 
         Ok(())
     }
 
     fn render(&mut self, _blending_factor: f64) -> Result<()> {
         self.clear_screen();
-        let mut projected: Vec<_> = self
-            .mesh
-            .iter()
-            .filter(|triangle| triangle.is_facing_viewer(self.camera.position))
-            .map(|triangle| triangle.project(self.camera.view_projection_matrix()))
-            .collect();
-        clip(&mut projected);
-        for triangle in projected
-            .iter()
-            .map(|triangle| triangle.divide_and_scale(self.size))
-        {
-            self.draw.fill_triangle(self.pixels.frame_mut(), &triangle);
-        }
-        self.draw.clear_depth_buffer();
+        self.pipeline.render(
+            self.pixels.frame_mut(),
+            &mut self.draw,
+            &self.scene,
+            &self.camera,
+            &self.lighting,
+            self.size,
+        );
         self.pixels.render()?;
         Ok(())
     }
@@ -152,7 +110,12 @@ impl App for Application {
                 let (width, height) = (size.width / self.scale, size.height / self.scale);
                 self.pixels.resize_surface(size.width, size.height)?;
                 self.pixels.resize_buffer(width, height)?;
-                self.draw = Draw::new(width as usize, height as usize, self.draw.textures.clone());
+                self.draw = Draw::new(
+                    width as usize,
+                    height as usize,
+                    self.scene.materials.clone(),
+                );
+                self.settings.apply(&mut self.pipeline, &mut self.draw);
                 self.camera.aspect_ratio = width as f32 / height as f32;
                 self.size = Vec2::new(width as f32, height as f32);
             }
@@ -169,34 +132,94 @@ impl App for Application {
 }
 
 fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let SceneData {
+        scene,
+        lighting,
+        camera: pose,
+        clear_color,
+        mut settings,
+    } = if args
+        .model
+        .extension()
+        .is_some_and(|extension| extension == "toml")
+    {
+        drawing::load_scene_file(&args.model)?
+    } else {
+        SceneData {
+            lighting: Lighting::default(),
+            camera: None,
+            clear_color: None,
+            settings: Default::default(),
+            scene: Scene::from(drawing::load_model_file(&args.model)?),
+        }
+    };
+
+    // Command line options take precedence over the scene file
+    let pose = pose.unwrap_or(DEFAULT_POSE);
+    let rotation = args.rotation.map_or(pose.rotation, |rotation| {
+        Vec2::new(rotation.x.to_radians(), rotation.y.to_radians())
+    });
+    let mut camera = Camera::new(args.position.unwrap_or(pose.position), rotation);
+    camera.fov = args.fov.map_or(pose.fov, f32::to_radians);
+    let clear_color = args
+        .clear_color
+        .or(clear_color)
+        .unwrap_or(DEFAULT_CLEAR_COLOR);
+    let scale = args
+        .scale
+        .or(settings.scale)
+        .unwrap_or(DEFAULT_SCALE)
+        .max(1);
+    settings.guard_band |= args.guard_band;
+
+    let [width, height] = [args.width / scale, args.height / scale];
+
+    if let Some(output) = args.output {
+        return headless::render_to_file(
+            &scene,
+            &camera,
+            &lighting,
+            &settings,
+            UVec2::new(width, height),
+            clear_color,
+            output,
+        );
+    }
+
     let event_loop = EventLoop::new()?;
 
     let window = Arc::new(
         WindowBuilder::new()
-            .with_inner_size(PhysicalSize::new(WIDTH, HEIGHT))
-            .with_fullscreen(FULLSCREEN.then_some(Fullscreen::Borderless(None)))
+            .with_inner_size(PhysicalSize::new(args.width, args.height))
+            .with_fullscreen((!args.windowed).then_some(Fullscreen::Borderless(None)))
             .build(&event_loop)?,
     );
 
     window.set_cursor_grab(CursorGrabMode::Confined)?;
     window.set_cursor_visible(false);
 
-    let [width, height] = [WIDTH / SCALE, HEIGHT / SCALE];
-
-    let ObjectData {
-        triangles: mesh,
-        textures,
-    } = mesh::load_from_obj_file(OBJECT_PATH)?;
-
-    let pixels = Pixels::new(width, height, SurfaceTexture::new(WIDTH, HEIGHT, &window))?;
+    let pixels = Pixels::new(
+        width,
+        height,
+        SurfaceTexture::new(args.width, args.height, &window),
+    )?;
 
+    let mut pipeline = Pipeline::new();
+    let mut draw = Draw::new(width as usize, height as usize, scene.materials.clone());
+    settings.apply(&mut pipeline, &mut draw);
     let app = Application {
-        mesh,
+        scene,
         pixels,
-        scale: SCALE,
-        camera: Camera::new(CAMERA_POSITION, CAMERA_ROTATION),
-        draw: Draw::new(width as usize, height as usize, textures),
-        size: Vec2::new(WIDTH as f32, HEIGHT as f32),
+        scale,
+        camera,
+        lighting,
+        draw,
+        pipeline,
+        settings,
+        size: Vec2::new(args.width as f32, args.height as f32),
+        clear_color,
     };
 
     let target_frame_time = Duration::from_secs_f32(TARGET_FRAME_TIME_SECONDS);
@@ -0,0 +1,374 @@
+//! Materials, their textures and loading them from MTL files.
+
+use anyhow::{bail, Context, Result};
+use glam::{Vec2, Vec3A};
+use image::{DynamicImage, ImageReader};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::{Index, IndexMut},
+    path::Path,
+};
+
+/// An RGBA image used to color triangles.
+#[derive(Debug, Clone)]
+pub struct Texture {
+    /// Width in pixels.
+    pub width: usize,
+    /// Height in pixels.
+    pub height: usize,
+    pixels: Vec<u8>,
+    translucent: bool,
+}
+
+impl Texture {
+    fn from_color(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            pixels: vec![r, g, b, 255],
+            translucent: false,
+        }
+    }
+
+    pub(crate) fn from_pixels(width: usize, height: usize, pixels: Vec<u8>) -> Self {
+        let translucent = pixels
+            .chunks_exact(4)
+            .any(|pixel| !matches!(pixel[3], 0 | 255));
+        Self {
+            width,
+            height,
+            pixels,
+            translucent,
+        }
+    }
+
+    /// Returns whether any pixel is partially transparent, as opposed to
+    /// fully opaque or fully transparent.
+    pub fn is_translucent(&self) -> bool {
+        self.translucent
+    }
+
+    /// Returns the color at (`x`, `y`), clamped to the edges of the texture.
+    pub fn get_pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let index = (x.min(self.width - 1) + self.width * y.min(self.height - 1)) * 4;
+        self.pixels[index..index + 4].try_into().unwrap()
+    }
+
+    /// Returns the color at texture coordinates `uv`, which should already
+    /// be wrapped into [0, 1).
+    pub fn sample(&self, uv: Vec2) -> [u8; 4] {
+        let x = (uv.x * self.width as f32) as usize;
+        let y = (uv.y * self.height as f32) as usize;
+        self.get_pixel(x, y)
+    }
+
+    /// Returns the color at texture coordinates `uv` like [`Texture::sample`],
+    /// without alpha and with each channel between 0 and 1.
+    pub fn sample_rgb(&self, uv: Vec2) -> Vec3A {
+        let [r, g, b, _] = self.sample(uv).map(f32::from);
+        Vec3A::new(r, g, b) / 255.0
+    }
+
+    /// Loads a texture from an image file.
+    pub fn try_from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+        Ok(Self::from_image(&image))
+    }
+
+    /// Creates a texture from a decoded image, flipped so that its bottom row
+    /// comes first, as texture coordinates start from the bottom.
+    pub fn from_image(image: &DynamicImage) -> Self {
+        let image = image.flipv().to_rgba8();
+        Texture::from_pixels(
+            image.width() as usize,
+            image.height() as usize,
+            image.into_raw(),
+        )
+    }
+}
+
+impl Default for Texture {
+    fn default() -> Self {
+        Self::from_color(255, 255, 255)
+    }
+}
+
+/// The surface properties of a triangle, following the MTL material model.
+#[derive(Debug, Clone)]
+pub struct Material {
+    /// Color reflected from ambient light (`Ka`).
+    pub ambient: Vec3A,
+    /// Color reflected from direct light (`Kd`).
+    pub diffuse: Vec3A,
+    /// Color of specular highlights (`Ks`).
+    pub specular: Vec3A,
+    /// Specular exponent, where higher values give smaller and sharper
+    /// highlights (`Ns`).
+    pub shininess: f32,
+    /// Opacity, from 0 for fully transparent to 1 for fully opaque (`d`, or
+    /// one minus `Tr`).
+    pub dissolve: f32,
+    /// Illumination model (`illum`), where 0 is unlit, 1 is diffuse only and
+    /// 2 and above add specular highlights.
+    pub illumination: u8,
+    /// Texture multiplied with the ambient color (`map_Ka`).
+    pub ambient_map: Option<Texture>,
+    /// Texture multiplied with the diffuse color (`map_Kd`), white if unset.
+    pub diffuse_map: Texture,
+    /// Texture multiplied with the specular color (`map_Ks`).
+    pub specular_map: Option<Texture>,
+    /// Texture multiplied with the shininess (`map_Ns`).
+    pub shininess_map: Option<Texture>,
+    /// Texture multiplied with the opacity (`map_d`).
+    pub dissolve_map: Option<Texture>,
+    /// Opacity below which pixels are cut out rather than blended, drawing
+    /// the rest as opaque, like glTF's `MASK` alpha mode. Overrides
+    /// [`Draw::alpha_cutoff`](crate::Draw::alpha_cutoff) when set.
+    pub alpha_cutoff: Option<f32>,
+    /// Height map perturbing the surface normal (`map_Bump` or `bump`).
+    pub bump_map: Option<Texture>,
+    /// Tangent space normal map (`norm`).
+    pub normal_map: Option<Texture>,
+}
+
+impl Material {
+    /// Creates an untextured material of a single diffuse color.
+    pub fn from_color(diffuse: Vec3A) -> Self {
+        Self {
+            diffuse,
+            ..Self::default()
+        }
+    }
+
+    /// Returns whether triangles with this material need to be blended with
+    /// what is behind them, which materials with an alpha cutoff never do.
+    pub fn is_translucent(&self) -> bool {
+        self.alpha_cutoff.is_none()
+            && (self.dissolve < 1.0
+                || self.diffuse_map.is_translucent()
+                || self.dissolve_map.is_some())
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            ambient: Vec3A::ONE,
+            diffuse: Vec3A::ONE,
+            specular: Vec3A::ZERO,
+            shininess: 0.0,
+            dissolve: 1.0,
+            illumination: 1,
+            ambient_map: None,
+            diffuse_map: Texture::default(),
+            specular_map: None,
+            shininess_map: None,
+            dissolve_map: None,
+            alpha_cutoff: None,
+            bump_map: None,
+            normal_map: None,
+        }
+    }
+}
+
+impl From<Texture> for Material {
+    fn from(diffuse_map: Texture) -> Self {
+        Self {
+            diffuse_map,
+            ..Self::default()
+        }
+    }
+}
+
+/// A handle to a material in [`Materials`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialId(pub u32);
+
+impl MaterialId {
+    /// The default material, present in every [`Materials`].
+    pub const DEFAULT: Self = Self(0);
+}
+
+/// A collection of materials, addressed by [`MaterialId`] and looked up by
+/// name.
+#[derive(Debug, Clone)]
+pub struct Materials {
+    materials: Vec<Material>,
+    ids: HashMap<String, MaterialId>,
+}
+
+impl Materials {
+    /// Creates a collection holding only the default material.
+    pub fn new() -> Self {
+        Self {
+            materials: vec![Material::default()],
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Adds a material under `name`, replacing any material of the same name,
+    /// and returns its handle.
+    pub fn insert(&mut self, name: &str, material: Material) -> MaterialId {
+        if let Some(&id) = self.ids.get(name) {
+            self[id] = material;
+            return id;
+        }
+        let id = MaterialId(self.materials.len() as u32);
+        self.materials.push(material);
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Moves every material of `other` after those already here, including
+    /// its default material, and returns the number their handles are offset
+    /// by. Names in `other` take over materials of the same name here.
+    pub fn append(&mut self, other: Materials) -> u32 {
+        let offset = self.materials.len() as u32;
+        self.materials.extend(other.materials);
+        for (name, id) in other.ids {
+            self.ids.insert(name, MaterialId(id.0 + offset));
+        }
+        offset
+    }
+
+    /// Returns the handle of the material called `name`.
+    pub fn id(&self, name: &str) -> Option<MaterialId> {
+        self.ids.get(name).copied()
+    }
+
+    /// Returns the material called `name`.
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.id(name).map(|id| &self[id])
+    }
+
+    /// Returns the number of materials, including the default one.
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    /// Returns whether there are no materials, which is never the case.
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+}
+
+impl Default for Materials {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<MaterialId> for Materials {
+    type Output = Material;
+
+    fn index(&self, id: MaterialId) -> &Material {
+        &self.materials[id.0 as usize]
+    }
+}
+
+impl IndexMut<MaterialId> for Materials {
+    fn index_mut(&mut self, id: MaterialId) -> &mut Material {
+        &mut self.materials[id.0 as usize]
+    }
+}
+
+/// Parses a color given as either one value for all channels or three.
+/// Colors given as a spectral curve (`spectral`) or in CIE XYZ (`xyz`) are
+/// not supported and return `None`, leaving the default color.
+fn parse_color<'a>(words: impl Iterator<Item = &'a str>) -> Result<Option<Vec3A>> {
+    let mut words = words.peekable();
+    if let Some(&"spectral" | &"xyz") = words.peek() {
+        return Ok(None);
+    }
+    let values: Vec<f32> = words.map(str::parse).collect::<Result<_, _>>()?;
+    match *values.as_slice() {
+        [value] => Ok(Some(Vec3A::splat(value))),
+        [r, g, b] => Ok(Some(Vec3A::new(r, g, b))),
+        _ => bail!("Colors must have one or three components"),
+    }
+}
+
+/// Loads the materials in an MTL file into `materials`.
+pub fn load_mtl_file(path: impl AsRef<Path>, materials: &mut Materials) -> Result<()> {
+    let path = path.as_ref();
+
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let mut material_id = None;
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let err = |message: &str| {
+            format!(
+                "In \"{}\" on line {}: {message}",
+                path.display(),
+                line_number + 1,
+            )
+        };
+
+        let line = line?;
+        if let Some('#') = line.chars().next() {
+            continue; // skip comments
+        }
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+
+        if command == "newmtl" {
+            let name = words.next().with_context(|| err("No material specified"))?;
+            material_id = Some(materials.insert(name, Material::default()));
+            continue;
+        }
+
+        let material = material_id
+            .map(|id| &mut materials[id])
+            .with_context(|| err("No material name specified"));
+
+        // Texture options such as `-bm 0.5` come before the file name, so only the last word is used
+        let load_texture = |words: std::str::SplitWhitespace| {
+            let image_string = words.last().with_context(|| err("No path specified"))?;
+            let image_path = path.with_file_name(image_string);
+            Texture::try_from_path(&image_path)
+                .with_context(|| err(&format!("Failed to load \"{}\"", image_path.display())))
+        };
+        let parse_number = |word: Option<&str>| -> Result<f32> {
+            let word = word.with_context(|| err("No value specified"))?;
+            word.parse()
+                .with_context(|| err(&format!("Invalid number \"{word}\"")))
+        };
+
+        match command {
+            "Ka" => {
+                if let Some(color) = parse_color(words).with_context(|| err("Invalid Ka"))? {
+                    material?.ambient = color;
+                }
+            }
+            "Kd" => {
+                if let Some(color) = parse_color(words).with_context(|| err("Invalid Kd"))? {
+                    material?.diffuse = color;
+                }
+            }
+            "Ks" => {
+                if let Some(color) = parse_color(words).with_context(|| err("Invalid Ks"))? {
+                    material?.specular = color;
+                }
+            }
+            "Ns" => material?.shininess = parse_number(words.next())?,
+            // Halo dissolve, fading towards the edges as seen from the camera, is not supported
+            "d" => material?.dissolve = parse_number(words.find(|&word| word != "-halo"))?,
+            "Tr" => material?.dissolve = 1.0 - parse_number(words.next())?,
+            "illum" => material?.illumination = parse_number(words.next())? as u8,
+            "map_Ka" => material?.ambient_map = Some(load_texture(words)?),
+            "map_Kd" => material?.diffuse_map = load_texture(words)?,
+            "map_Ks" => material?.specular_map = Some(load_texture(words)?),
+            "map_Ns" => material?.shininess_map = Some(load_texture(words)?),
+            "map_d" => material?.dissolve_map = Some(load_texture(words)?),
+            "map_Bump" | "map_bump" | "bump" => material?.bump_map = Some(load_texture(words)?),
+            "norm" => material?.normal_map = Some(load_texture(words)?),
+            _ => (),
+        }
+    }
+    Ok(())
+}
@@ -1,105 +1,408 @@
-use crate::geometry::{Triangle, Vertex};
-use glam::Vec3A;
-use image::ImageReader;
+//! Indexed triangle meshes and loading them from OBJ files, or from any of
+//! the supported model formats.
+
+use crate::{
+    bvh::Bvh,
+    geometry::Vertex,
+    gltf::load_gltf_file,
+    material::{load_mtl_file, Material, MaterialId, Materials},
+    ply::load_ply_file,
+    stl::{is_binary_stl, load_stl_file},
+};
+use anyhow::{bail, Context, Result};
+use glam::{Vec2, Vec3A, Vec4};
 use std::{
     collections::HashMap,
+    ffi::OsStr,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read},
+    ops::Range,
     path::Path,
 };
-use win_loop::anyhow::{bail, Context, Result};
 
 const DEFAULT_NORMAL: Vec3A = Vec3A::Y;
 const DEFAULT_TEXTURE: Vec3A = Vec3A::ZERO;
 
-#[derive(Debug, Clone)]
-pub struct Texture {
-    pub width: usize,
-    pub height: usize,
-    pixels: Vec<u8>,
+/// A range of a [`Mesh`]'s triangles drawn with the same material.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    /// Material the triangles are drawn with.
+    pub material: MaterialId,
+    /// Range of the triangles in [`Mesh::indices`].
+    pub triangles: Range<usize>,
 }
 
-impl Texture {
-    fn from_color(r: u8, g: u8, b: u8) -> Self {
-        Self {
-            width: 1,
-            height: 1,
-            pixels: vec![r, g, b, 255],
+/// A named range of a [`Mesh`]'s triangles, such as an object or group of an
+/// OBJ file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    /// Name of the group.
+    pub name: String,
+    /// Range of the triangles in [`Mesh::indices`], made up of whole submeshes.
+    pub triangles: Range<usize>,
+}
+
+/// A triangle mesh whose triangles index into a shared vertex buffer, so that
+/// each vertex is stored and transformed only once.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    /// Every distinct vertex of the mesh.
+    pub vertices: Vec<Vertex>,
+    /// Indices into `vertices` of the corners of each triangle.
+    pub indices: Vec<[u32; 3]>,
+    /// Ranges of `indices` by material, covering every triangle once.
+    pub submeshes: Vec<Submesh>,
+    /// Named ranges of `indices`, in the order the groups first appear.
+    /// Triangles outside of every group come before them.
+    pub groups: Vec<Group>,
+    /// Hierarchy of bounding boxes over the triangles, to be rebuilt with
+    /// [`Bvh::new`] after changing `vertices` or `indices`.
+    pub bvh: Bvh,
+}
+
+impl Mesh {
+    /// Builds a mesh from loose triangles and their materials, merging
+    /// identical vertices and grouping the triangles by material while
+    /// keeping their order within each material.
+    pub fn from_triangles(triangles: impl IntoIterator<Item = ([Vertex; 3], MaterialId)>) -> Self {
+        Self::from_grouped_triangles(
+            triangles
+                .into_iter()
+                .map(|(vertices, material)| (vertices, material, None)),
+            &[],
+        )
+    }
+
+    /// Builds a mesh like [`Mesh::from_triangles`] from triangles that may
+    /// belong to one of the groups named in `names`, by index. The triangles
+    /// are ordered by group first, so that each group is a single range.
+    pub fn from_grouped_triangles(
+        triangles: impl IntoIterator<Item = ([Vertex; 3], MaterialId, Option<usize>)>,
+        names: &[String],
+    ) -> Self {
+        let mut triangles: Vec<_> = triangles.into_iter().collect();
+        triangles.sort_by_key(|&(_, material, group)| (group, material));
+
+        let mut mesh = Self::default();
+        let mut vertex_indices = HashMap::new();
+        let mut previous_group = None;
+        for (vertices, material, group) in triangles {
+            let triangle = vertices.map(|vertex| {
+                let key = (
+                    [vertex.position, vertex.normal, vertex.texture]
+                        .map(|vector| vector.to_array().map(f32::to_bits)),
+                    vertex.color.to_array().map(f32::to_bits),
+                );
+                *vertex_indices.entry(key).or_insert_with(|| {
+                    mesh.vertices.push(vertex);
+                    (mesh.vertices.len() - 1) as u32
+                })
+            });
+
+            let index = mesh.indices.len();
+            let same_group = previous_group == Some(group);
+            match mesh.submeshes.last_mut() {
+                Some(submesh) if same_group && submesh.material == material => {
+                    submesh.triangles.end += 1;
+                }
+                _ => mesh.submeshes.push(Submesh {
+                    material,
+                    triangles: index..index + 1,
+                }),
+            }
+            if let Some(group) = group {
+                match mesh.groups.last_mut() {
+                    Some(last) if same_group => last.triangles.end += 1,
+                    _ => mesh.groups.push(Group {
+                        name: names[group].clone(),
+                        triangles: index..index + 1,
+                    }),
+                }
+            }
+            previous_group = Some(group);
+            mesh.indices.push(triangle);
         }
+        mesh.bvh = Bvh::new(&mesh.vertices, &mesh.indices);
+        mesh
     }
 
-    pub fn get_pixel(&self, x: usize, y: usize) -> [u8; 4] {
-        let index = (x.min(self.width - 1) + self.width * y.min(self.height - 1)) * 4;
-        self.pixels[index..index + 4].try_into().unwrap()
+    /// Returns a new mesh of the triangles at `triangles` in `indices`, with
+    /// their materials and what remains of their groups, so that part of a
+    /// model such as one of its groups can be placed or hidden on its own.
+    pub fn extract(&self, triangles: Range<usize>) -> Self {
+        let names: Vec<String> = self.groups.iter().map(|group| group.name.clone()).collect();
+        Self::from_grouped_triangles(
+            triangles.map(|i| {
+                let group = self
+                    .groups
+                    .partition_point(|group| group.triangles.end <= i);
+                let group = self
+                    .groups
+                    .get(group)
+                    .is_some_and(|found| found.triangles.contains(&i))
+                    .then_some(group);
+                (self.triangle(self.indices[i]), self.material(i), group)
+            }),
+            &names,
+        )
     }
 
-    pub fn try_from_path(path: impl AsRef<Path>) -> Result<Self> {
-        let image = ImageReader::open(path)?
-            .with_guessed_format()?
-            .decode()?
-            .flipv()
-            .to_rgba8();
-        let texture = Texture {
-            width: image.width() as usize,
-            height: image.height() as usize,
-            pixels: image.into_raw(),
-        };
-        Ok(texture)
+    /// Returns the number of triangles in the mesh.
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns the corners of a triangle.
+    pub fn triangle(&self, indices: [u32; 3]) -> [Vertex; 3] {
+        indices.map(|i| self.vertices[i as usize])
+    }
+
+    /// Returns the material of the triangle at `index` in `indices`.
+    pub fn material(&self, index: usize) -> MaterialId {
+        let submesh = self
+            .submeshes
+            .partition_point(|submesh| submesh.triangles.end <= index);
+        self.submeshes[submesh].material
+    }
+
+    /// Returns the first group called `name`.
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name == name)
     }
 }
 
-impl Default for Texture {
+/// The contents of a model file.
+pub struct ObjectData {
+    /// Every face of the model, triangulated.
+    pub mesh: Mesh,
+    /// Materials referenced by the mesh.
+    pub materials: Materials,
+}
+
+/// Settings for [`load_from_obj_file_with_options`].
+#[derive(Copy, Clone, Debug)]
+pub struct ObjOptions {
+    /// Whether faces before any `s` statement are smoothed as if they were in
+    /// a smoothing group, since files without normals often have no smoothing
+    /// groups either. Otherwise they get flat normals.
+    pub smooth_by_default: bool,
+    /// Largest angle in radians between two faces for which normals are
+    /// smoothed across their shared vertices, keeping sharper edges creased.
+    /// `None` smooths across every edge within a smoothing group.
+    pub crease_angle: Option<f32>,
+}
+
+impl Default for ObjOptions {
     fn default() -> Self {
-        Self::from_color(255, 255, 255)
+        Self {
+            smooth_by_default: true,
+            crease_angle: None,
+        }
     }
 }
 
-pub fn load_mtl_file(path: impl AsRef<Path>, map: &mut HashMap<String, Texture>) -> Result<()> {
-    let path = path.as_ref();
+/// A triangle with vertices lacking normals, to be filled in once every face
+/// has been read.
+pub(crate) struct MissingNormals {
+    /// Index of the triangle among those read so far.
+    pub(crate) triangle: usize,
+    /// Position index of each vertex.
+    pub(crate) positions: [usize; 3],
+    /// Whether each vertex lacks a normal.
+    pub(crate) missing: [bool; 3],
+    /// Smoothing group of the face, or 0 for flat shading.
+    pub(crate) smoothing_group: u32,
+}
 
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let mut material_name = None;
+/// Splits a polygon face into triangles by ear clipping, returning the
+/// indices of their corners in `positions`, wound the same way as the face.
+///
+/// The polygon is projected onto its plane, found with Newell's method, so
+/// that concave faces are split inside their outline. Degenerate faces, and
+/// what remains of faces without ears due to rounding, are split into fans.
+pub(crate) fn triangulate(positions: &[Vec3A]) -> Vec<[usize; 3]> {
+    let n = positions.len();
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
 
-    for line in reader.lines() {
-        let line = line?;
-        if let Some('#') = line.chars().next() {
-            continue; // skip comments
-        }
-        let mut words = line.split_whitespace();
-        let Some(command) = words.next() else {
-            continue;
+    let normal = (0..n)
+        .map(|i| positions[i].cross(positions[(i + 1) % n]))
+        .sum::<Vec3A>();
+    let Some(normal) = normal.try_normalize() else {
+        return (1..n - 1).map(|i| [0, i, i + 1]).collect();
+    };
+    // Counterclockwise faces stay counterclockwise in this basis
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+    let points: Vec<Vec2> = positions
+        .iter()
+        .map(|&position| Vec2::new(position.dot(u), position.dot(v)))
+        .collect();
+
+    let (min, max) = points
+        .iter()
+        .fold((points[0], points[0]), |(min, max), &p| {
+            (min.min(p), max.max(p))
+        });
+    // Corners with less turn than this are treated as straight
+    let epsilon = 1e-6 * (max - min).length_squared();
+    let turn = |[a, b, c]: [usize; 3]| (points[b] - points[a]).perp_dot(points[c] - points[b]);
+    let contains = |[a, b, c]: [usize; 3], p: Vec2| {
+        [(a, b), (b, c), (c, a)]
+            .iter()
+            .all(|&(from, to)| (points[to] - points[from]).perp_dot(p - points[from]) >= 0.0)
+    };
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let corners = |i: usize| {
+            [
+                remaining[(i + len - 1) % len],
+                remaining[i],
+                remaining[(i + 1) % len],
+            ]
         };
+        let is_ear = |i: usize| {
+            let triangle = corners(i);
+            turn(triangle) > epsilon
+                && remaining.iter().all(|&j| {
+                    triangle.contains(&j)
+                        || triangle.iter().any(|&k| points[k] == points[j])
+                        || !contains(triangle, points[j])
+                })
+        };
+        // Without an ear, the sharpest convex corner is the least bad to cut
+        let ear = (0..len).find(|&i| is_ear(i)).unwrap_or_else(|| {
+            (0..len)
+                .max_by(|&a, &b| turn(corners(a)).total_cmp(&turn(corners(b))))
+                .unwrap_or(0)
+        });
+        triangles.push(corners(ear));
+        remaining.remove(ear);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
 
-        match command {
-            "newmtl" => {
-                let name = words
-                    .next()
-                    .with_context(|| "No material specified")?
-                    .to_string();
-                material_name = Some(name);
-            }
-            "map_Kd" => {
-                let name = material_name
-                    .take()
-                    .with_context(|| "No material name specified")?;
-                let image_string = words.next().with_context(|| "No path specified")?;
-                let image_path = path.with_file_name(image_string);
-                let image = Texture::try_from_path(image_path)?;
-                map.insert(name, image);
-            }
-            _ => (),
+    // Starts each triangle at its lowest index, keeping its winding, so that
+    // quads are split as [0, 1, 3] and [1, 2, 3] where possible
+    for triangle in &mut triangles {
+        let first = (0..3).min_by_key(|&i| triangle[i]).unwrap_or(0);
+        triangle.rotate_left(first);
+    }
+    triangles
+}
+
+/// Returns the angle at `corner` of the triangle `vertices`.
+fn corner_angle(vertices: [Vec3A; 3], corner: usize) -> f32 {
+    let origin = vertices[corner];
+    let a = vertices[(corner + 1) % 3] - origin;
+    let b = vertices[(corner + 2) % 3] - origin;
+    a.angle_between(b)
+}
+
+/// Fills in normals missing from the file. Vertices in a smoothing group get
+/// the average of the normals of the faces sharing their position within that
+/// group, weighted by the angle of each face at that vertex, and others get
+/// the normal of their face.
+pub(crate) fn generate_normals(
+    triangles: &mut [([Vertex; 3], MaterialId)],
+    missing: &[MissingNormals],
+    options: ObjOptions,
+) {
+    let face_normals: Vec<Vec3A> = missing
+        .iter()
+        .map(|face| {
+            let [a, b, c] = triangles[face.triangle].0.map(|v| v.position);
+            (b - a).cross(c - a).normalize_or_zero()
+        })
+        .collect();
+
+    let mut shared: HashMap<(usize, u32), Vec<(usize, f32)>> = HashMap::new();
+    for (i, face) in missing.iter().enumerate() {
+        if face.smoothing_group == 0 {
+            continue;
+        }
+        let positions = triangles[face.triangle].0.map(|v| v.position);
+        for (corner, &position) in face.positions.iter().enumerate() {
+            shared
+                .entry((position, face.smoothing_group))
+                .or_default()
+                .push((i, corner_angle(positions, corner)));
+        }
+    }
+
+    let min_cosine = options.crease_angle.map(f32::cos);
+    for (i, face) in missing.iter().enumerate() {
+        let face_normal = face_normals[i];
+        for corner in (0..3).filter(|&corner| face.missing[corner]) {
+            let normal = if face.smoothing_group == 0 {
+                face_normal
+            } else {
+                shared[&(face.positions[corner], face.smoothing_group)]
+                    .iter()
+                    .filter(|&&(j, _)| {
+                        min_cosine.is_none_or(|min| face_normal.dot(face_normals[j]) >= min)
+                    })
+                    .map(|&(j, angle)| face_normals[j] * angle)
+                    .sum::<Vec3A>()
+                    .normalize_or_zero()
+            };
+            triangles[face.triangle].0[corner].normal = if normal == Vec3A::ZERO {
+                DEFAULT_NORMAL
+            } else {
+                normal
+            };
         }
     }
-    Ok(())
 }
 
-pub struct ObjectData {
-    pub triangles: Vec<Triangle>,
-    pub textures: HashMap<String, Texture>,
+/// Loads a model from an OBJ, glTF, STL or PLY file, picking the format by
+/// the file's extension, or by how the file starts if the extension is none
+/// of theirs.
+pub fn load_model_file(path: impl AsRef<Path>) -> Result<ObjectData> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("obj") => return load_from_obj_file(path),
+        Some("gltf" | "glb") => return load_gltf_file(path),
+        Some("stl") => return load_stl_file(path),
+        Some("ply") => return load_ply_file(path),
+        _ => (),
+    }
+
+    // Enough for the magic numbers and the triangle count of a binary STL file
+    let mut start = Vec::with_capacity(84);
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open \"{}\"", path.display()))?;
+    file.by_ref().take(84).read_to_end(&mut start)?;
+    let length = file.metadata()?.len();
+    if start.starts_with(b"ply") {
+        load_ply_file(path)
+    } else if start.starts_with(b"glTF") || start.trim_ascii_start().starts_with(b"{") {
+        load_gltf_file(path)
+    } else if start.starts_with(b"solid") || is_binary_stl(&start, length) {
+        load_stl_file(path)
+    } else {
+        load_from_obj_file(path)
+    }
 }
 
+/// Loads a model from an OBJ file, along with the MTL files it references,
+/// with the default [`ObjOptions`].
 pub fn load_from_obj_file(path: impl AsRef<Path>) -> Result<ObjectData> {
+    load_from_obj_file_with_options(path, ObjOptions::default())
+}
+
+/// Loads a model from an OBJ file, along with the MTL files it references.
+pub fn load_from_obj_file_with_options(
+    path: impl AsRef<Path>,
+    options: ObjOptions,
+) -> Result<ObjectData> {
     let path = path.as_ref();
 
     // Initialize with a dummy value to offset one-based indexing
@@ -108,14 +411,25 @@ pub fn load_from_obj_file(path: impl AsRef<Path>) -> Result<ObjectData> {
     let mut normals = vec![Vec3A::default()];
 
     let mut triangles = vec![];
-    let mut textures = HashMap::new();
+    let mut materials = Materials::new();
 
     // Some default materials for debugging
-    textures.insert("cyan".to_string(), Texture::from_color(0, 255, 255));
-    textures.insert("magenta".to_string(), Texture::from_color(255, 0, 255));
-    textures.insert("yellow".to_string(), Texture::from_color(255, 255, 0));
+    for (name, color) in [
+        ("cyan", Vec3A::new(0.0, 1.0, 1.0)),
+        ("magenta", Vec3A::new(1.0, 0.0, 1.0)),
+        ("yellow", Vec3A::new(1.0, 1.0, 0.0)),
+    ] {
+        materials.insert(name, Material::from_color(color));
+    }
 
+    // Material names are resolved when used, as libraries may come after `usemtl`
+    let mut material_names: Vec<String> = vec![];
     let mut current_material = None;
+    let mut group_names: Vec<String> = vec![];
+    let mut current_group = None;
+    let mut triangle_groups = vec![];
+    let mut smoothing_group = u32::from(options.smooth_by_default);
+    let mut missing_normals = vec![];
 
     let reader = BufReader::new(File::open(path)?);
     for (line_number, line) in reader.lines().enumerate() {
@@ -144,13 +458,46 @@ pub fn load_from_obj_file(path: impl AsRef<Path>) -> Result<ObjectData> {
             "mtllib" => {
                 let library_string = words.next().with_context(|| err("No path provided"))?;
                 let library_path = path.with_file_name(library_string);
-                load_mtl_file(library_path, &mut textures)?;
+                load_mtl_file(library_path, &mut materials)?;
             }
             "usemtl" => {
                 let material_name = words
                     .next()
                     .with_context(|| err("No material name provided"))?;
-                current_material = Some(material_name);
+                let index = material_names
+                    .iter()
+                    .position(|name| *name == material_name)
+                    .unwrap_or_else(|| {
+                        material_names.push(material_name);
+                        material_names.len() - 1
+                    });
+                current_material = Some(index);
+            }
+            "o" | "g" => {
+                // Objects and groups alike start a new group, unless unnamed
+                let name = words.collect::<Vec<_>>().join(" ");
+                current_group = (!name.is_empty()).then(|| {
+                    group_names
+                        .iter()
+                        .position(|group| *group == name)
+                        .unwrap_or_else(|| {
+                            group_names.push(name);
+                            group_names.len() - 1
+                        })
+                });
+            }
+            "s" => {
+                let group = words
+                    .next()
+                    .with_context(|| err("No smoothing group provided"))?;
+                smoothing_group = match group.as_str() {
+                    "off" => 0,
+                    // Some exporters write a single smoothing group as `s on`
+                    "on" => 1,
+                    _ => group
+                        .parse()
+                        .with_context(|| err(&format!("Invalid smoothing group \"{group}\"")))?,
+                };
             }
             "v" | "vn" | "vt" => {
                 let destination = match command.as_ref() {
@@ -174,57 +521,105 @@ pub fn load_from_obj_file(path: impl AsRef<Path>) -> Result<ObjectData> {
                 destination.push(Vec3A::from_slice(&points));
             }
             "f" => {
-                let mut triangle = Vec::with_capacity(4);
+                let mut face = Vec::with_capacity(4);
+
+                // Resolves a one-based index, or one counting back from the
+                // latest element if negative, into data starting with a dummy
+                let resolve = |index: &str, len: usize, kind: &str| -> Result<usize> {
+                    let parsed: isize = index
+                        .parse()
+                        .with_context(|| err(&format!("Invalid {kind} index \"{index}\"")))?;
+                    let resolved = if parsed < 0 {
+                        len as isize + parsed
+                    } else {
+                        parsed
+                    };
+                    if !(1..len as isize).contains(&resolved) {
+                        bail!(err(&format!(
+                            "The {kind} index {parsed} is out of range, with {} defined so far",
+                            len - 1
+                        )));
+                    }
+                    Ok(resolved as usize)
+                };
 
                 for word in words {
-                    let vertex_data: Vec<usize> = word
-                        .split('/')
-                        .filter(|s| !s.is_empty())
-                        .map(str::parse)
-                        .collect::<Result<_, _>>()?;
-
-                    let (position, texture, normal) = match *vertex_data.as_slice() {
-                        [v, vt, vn] => (vertices[v], texture_coordinates[vt], normals[vn]),
-                        [v, vt] => (vertices[v], texture_coordinates[vt], DEFAULT_NORMAL),
-                        [v] => (vertices[v], DEFAULT_TEXTURE, DEFAULT_NORMAL),
-                        _ => bail!(err("Invalid number of face arguments")),
+                    let fields: Vec<&str> = word.split('/').collect();
+                    let (v, vt, vn) = match *fields.as_slice() {
+                        [v] => (v, "", ""),
+                        [v, vt] => (v, vt, ""),
+                        [v, vt, vn] => (v, vt, vn),
+                        _ => bail!(err(&format!("Invalid face vertex \"{word}\""))),
                     };
 
-                    triangle.push(Vertex {
-                        position,
-                        normal,
+                    let v = resolve(v, vertices.len(), "position")?;
+                    let texture = if vt.is_empty() {
+                        DEFAULT_TEXTURE
+                    } else {
+                        texture_coordinates
+                            [resolve(vt, texture_coordinates.len(), "texture coordinate")?]
+                    };
+                    let normal = if vn.is_empty() {
+                        None
+                    } else {
+                        Some(normals[resolve(vn, normals.len(), "normal")?])
+                    };
+
+                    let vertex = Vertex {
+                        position: vertices[v],
+                        normal: normal.unwrap_or(DEFAULT_NORMAL),
                         texture,
-                    });
+                        color: Vec4::ONE,
+                    };
+                    face.push((v, vertex, normal.is_none()));
                 }
 
-                let texture = current_material.clone().unwrap_or_default();
-
-                match triangle.len() {
-                    3 => {
-                        triangles.push(Triangle::new(
-                            [triangle[0], triangle[1], triangle[2]],
-                            texture.as_str(),
-                        ));
-                    }
-                    4 => {
-                        triangles.push(Triangle::new(
-                            [triangle[0], triangle[1], triangle[3]],
-                            texture.as_str(),
-                        ));
-                        triangles.push(Triangle::new(
-                            [triangle[1], triangle[2], triangle[3]],
-                            texture.as_str(),
-                        ));
+                if face.len() < 3 {
+                    bail!(err("Faces must have at least 3 vertices"));
+                }
+                let positions: Vec<Vec3A> =
+                    face.iter().map(|(_, vertex, _)| vertex.position).collect();
+                for corners in triangulate(&positions) {
+                    let corners = corners.map(|i| face[i]);
+                    let missing = corners.map(|(_, _, missing)| missing);
+                    if missing.contains(&true) {
+                        missing_normals.push(MissingNormals {
+                            triangle: triangles.len(),
+                            positions: corners.map(|(v, _, _)| v),
+                            missing,
+                            smoothing_group,
+                        });
                     }
-                    _ => bail!(err("Only faces of 3 and 4 vertices are supported")),
+                    let vertices = corners.map(|(_, vertex, _)| vertex);
+                    triangles.push((vertices, current_material));
+                    triangle_groups.push(current_group);
                 }
             }
             _ => (),
         }
     }
 
+    let material_ids: Vec<MaterialId> = material_names
+        .iter()
+        .map(|name| materials.id(name).unwrap_or(MaterialId::DEFAULT))
+        .collect();
+    let mut triangles: Vec<([Vertex; 3], MaterialId)> = triangles
+        .into_iter()
+        .map(|(vertices, material)| {
+            let id = material.map_or(MaterialId::DEFAULT, |index: usize| material_ids[index]);
+            (vertices, id)
+        })
+        .collect();
+    generate_normals(&mut triangles, &missing_normals, options);
+
     Ok(ObjectData {
-        triangles,
-        textures,
+        mesh: Mesh::from_grouped_triangles(
+            triangles
+                .into_iter()
+                .zip(triangle_groups)
+                .map(|((vertices, material), group)| (vertices, material, group)),
+            &group_names,
+        ),
+        materials,
     })
 }
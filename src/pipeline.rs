@@ -0,0 +1,284 @@
+//! The stages between a mesh in world space and pixels on screen.
+
+use crate::{
+    bvh::Frustum,
+    camera::Camera,
+    draw::Draw,
+    geometry::{normal_matrix, ProjectedTriangle, ProjectedVertex, ScreenTriangle},
+    light::Lighting,
+    mesh::Mesh,
+    scene::Scene,
+};
+use glam::{Mat4, Vec2, Vec3A, Vec4};
+
+pub(crate) const CLIPPING_PLANES: [Vec4; 6] = [
+    Vec4::new(0.0, 0.0, 1.0, 1.0),  // Near
+    Vec4::new(0.0, 0.0, -1.0, 0.0), // Far
+    Vec4::new(1.0, 0.0, 0.0, 1.0),  // Left
+    Vec4::new(-1.0, 0.0, 0.0, 1.0), // Right
+    Vec4::new(0.0, -1.0, 0.0, 1.0), // Top
+    Vec4::new(0.0, 1.0, 0.0, 1.0),  // Bottom
+];
+
+/// How far the guard band extends around the screen, as a multiple of the
+/// screen's size. Vertices this far out still fit comfortably in the
+/// rasterizer's fixed-point coordinates.
+const GUARD_BAND: f32 = 8.0;
+
+/// Returns whether `vertex` is on the inner side of `plane`.
+fn is_inside(plane: Vec4, vertex: &ProjectedVertex) -> bool {
+    vertex.position.dot(plane).is_sign_positive()
+}
+
+fn intersection(plane: Vec4, a: ProjectedVertex, b: ProjectedVertex) -> ProjectedVertex {
+    let s = plane.dot(a.position) / (plane.dot(a.position) - plane.dot(b.position));
+    a.lerp(b, s)
+}
+
+/// Most vertices a triangle can have after being clipped by every plane, as
+/// each plane adds at most one.
+const MAX_CLIPPED_VERTICES: usize = 3 + CLIPPING_PLANES.len();
+
+/// Clips clip-space triangles against the view frustum into `output`,
+/// splitting those crossing its planes and leaving out those entirely outside
+/// of it.
+///
+/// With `guard_band`, triangles crossing the sides of the screen are only
+/// split if they also reach beyond a band around it, leaving the rest to the
+/// rasterizer, which only ever visits pixels on the screen. The near and far
+/// planes are always clipped against.
+pub fn clip(
+    triangles: &[ProjectedTriangle],
+    output: &mut Vec<ProjectedTriangle>,
+    guard_band: bool,
+) {
+    let mut planes = CLIPPING_PLANES;
+    if guard_band {
+        // Moves the sides, after the near and far planes, out to the guard band
+        for plane in &mut planes[2..] {
+            plane.w = GUARD_BAND;
+        }
+    }
+
+    for triangle in triangles {
+        // Entirely outside of the frustum, guard band or not
+        if CLIPPING_PLANES.iter().any(|&plane| {
+            triangle
+                .vertices
+                .iter()
+                .all(|vertex| !is_inside(plane, vertex))
+        }) {
+            continue;
+        }
+        if planes.iter().all(|&plane| {
+            triangle
+                .vertices
+                .iter()
+                .all(|vertex| is_inside(plane, vertex))
+        }) {
+            output.push(*triangle);
+            continue;
+        }
+
+        // Sutherland-Hodgman, one plane at a time, between two fixed buffers
+        let mut polygon = [ProjectedVertex::default(); MAX_CLIPPED_VERTICES];
+        let mut clipped = [ProjectedVertex::default(); MAX_CLIPPED_VERTICES];
+        polygon[..3].copy_from_slice(&triangle.vertices);
+        let mut length = 3;
+        for plane in planes {
+            let mut clipped_length = 0;
+            for i in 0..length {
+                let a = polygon[i];
+                let b = polygon[(i + 1) % length];
+                let a_inside = is_inside(plane, &a);
+                let b_inside = is_inside(plane, &b);
+                if a_inside {
+                    clipped[clipped_length] = a;
+                    clipped_length += 1;
+                }
+                if a_inside != b_inside {
+                    clipped[clipped_length] = intersection(plane, a, b);
+                    clipped_length += 1;
+                }
+            }
+            std::mem::swap(&mut polygon, &mut clipped);
+            length = clipped_length;
+            if length < 3 {
+                break;
+            }
+        }
+
+        // The clipped polygon is convex, so it can be split into a fan
+        for i in 1..length.saturating_sub(1) {
+            output.push(ProjectedTriangle {
+                vertices: [polygon[0], polygon[i], polygon[i + 1]],
+                material: triangle.material,
+            });
+        }
+    }
+}
+
+/// Counts of the work done by the last call to [`Pipeline::render`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    /// Number of vertices transformed into clip space.
+    pub vertices: usize,
+    /// Number of corners of the triangles using those vertices, which is how
+    /// many transforms there would be if shared vertices were not reused.
+    pub corners: usize,
+    /// Number of triangles in view and facing the camera, passed to [`clip`].
+    pub triangles: usize,
+    /// Number of triangles left after clipping, counting each piece of a
+    /// split triangle.
+    pub clipped: usize,
+}
+
+/// Projects, clips and rasterizes scenes, keeping the buffers between each
+/// stage across frames so that once they have grown to fit a scene, drawing
+/// it again does not allocate.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    /// Matrix transforming each node of the scene into world space.
+    world_matrices: Vec<Mat4>,
+    /// Squared distance from the camera and index of each node with a mesh,
+    /// nearest first.
+    node_order: Vec<(f32, usize)>,
+    /// Vertices of the mesh being projected, projected when first used.
+    vertices: Vec<ProjectedVertex>,
+    /// The value of `generation` when each vertex was last projected, so that
+    /// vertices left over from earlier meshes are projected again.
+    vertex_generations: Vec<u64>,
+    /// Incremented for every mesh projected.
+    generation: u64,
+    projected: Vec<ProjectedTriangle>,
+    clipped: Vec<ProjectedTriangle>,
+    triangles: Vec<ScreenTriangle>,
+    stats: PipelineStats,
+    /// Whether to leave triangles crossing the sides of the screen to the
+    /// rasterizer instead of splitting them, as described in [`clip`].
+    pub guard_band: bool,
+}
+
+impl Pipeline {
+    /// Creates a pipeline with empty buffers, clipping without a guard band.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Projects, clips and rasterizes every mesh in `scene` as seen from
+    /// `camera` and lit by `lighting` into `frame`, an RGBA buffer of `size`
+    /// pixels. `draw` must hold the scene's materials.
+    ///
+    /// Meshes are drawn nearest first, and parts of them outside of the view
+    /// frustum are skipped using their [`Bvh`](crate::Bvh), which also orders
+    /// the remaining triangles from front to back so that fewer hidden pixels
+    /// are shaded.
+    pub fn render(
+        &mut self,
+        frame: &mut [u8],
+        draw: &mut Draw,
+        scene: &Scene,
+        camera: &Camera,
+        lighting: &Lighting,
+        size: Vec2,
+    ) {
+        self.stats = PipelineStats::default();
+        let view_projection_matrix = camera.view_projection_matrix();
+        scene.world_matrices(&mut self.world_matrices);
+
+        self.node_order.clear();
+        for (i, node) in scene.nodes().iter().enumerate() {
+            if let Some(mesh) = node.mesh {
+                let center = scene.meshes[mesh.0 as usize].bvh.aabb().center();
+                let world_center = self.world_matrices[i].transform_point3a(center);
+                let distance = camera.position.distance_squared(world_center);
+                self.node_order.push((distance, i));
+            }
+        }
+        self.node_order.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        self.projected.clear();
+        for index in 0..self.node_order.len() {
+            let node = self.node_order[index].1;
+            let Some(mesh) = scene.nodes()[node].mesh else {
+                continue;
+            };
+            self.project_mesh(
+                &scene.meshes[mesh.0 as usize],
+                self.world_matrices[node],
+                view_projection_matrix,
+                camera.position,
+            );
+        }
+
+        self.clipped.clear();
+        clip(&self.projected, &mut self.clipped, self.guard_band);
+        self.stats.triangles = self.projected.len();
+        self.stats.clipped = self.clipped.len();
+
+        self.triangles.clear();
+        self.triangles.extend(
+            self.clipped
+                .iter()
+                .map(|triangle| triangle.divide_and_scale(size)),
+        );
+        draw.draw_triangles(frame, &self.triangles, lighting, camera.position);
+        draw.clear_depth_buffer();
+    }
+
+    /// Returns what happened during the last frame rendered.
+    pub fn stats(&self) -> PipelineStats {
+        self.stats
+    }
+
+    /// Adds the triangles of `mesh`, placed in the world by `model_matrix`,
+    /// that are in view and facing `eye` to the projected triangles. Each
+    /// vertex they use is transformed once, however many triangles share it.
+    fn project_mesh(
+        &mut self,
+        mesh: &Mesh,
+        model_matrix: Mat4,
+        view_projection_matrix: Mat4,
+        eye: Vec3A,
+    ) {
+        let normal_matrix = normal_matrix(model_matrix);
+        let frustum = Frustum::from_matrix(view_projection_matrix * model_matrix);
+        let model_eye = model_matrix.inverse().transform_point3a(eye);
+        // Mirroring turns triangles inside out, so the other side faces the viewer
+        let mirrored = model_matrix.determinant() < 0.0;
+
+        self.generation += 1;
+        if self.vertices.len() < mesh.vertices.len() {
+            self.vertices
+                .resize(mesh.vertices.len(), ProjectedVertex::default());
+            self.vertex_generations.resize(mesh.vertices.len(), 0);
+        }
+
+        mesh.bvh.traverse(&frustum, model_eye, |triangles| {
+            for &index in triangles {
+                let index = index as usize;
+                let vertices = mesh.indices[index].map(|i| {
+                    let i = i as usize;
+                    if self.vertex_generations[i] != self.generation {
+                        self.vertex_generations[i] = self.generation;
+                        self.stats.vertices += 1;
+                        self.vertices[i] = mesh.vertices[i].project(
+                            model_matrix,
+                            normal_matrix,
+                            view_projection_matrix,
+                        );
+                    }
+                    self.vertices[i]
+                });
+                self.stats.corners += vertices.len();
+                let triangle = ProjectedTriangle {
+                    vertices,
+                    material: mesh.material(index),
+                };
+                if triangle.is_facing_viewer(eye) != mirrored {
+                    self.projected.push(triangle);
+                }
+            }
+        });
+    }
+}
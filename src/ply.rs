@@ -0,0 +1,489 @@
+//! Loading models from PLY files.
+
+use crate::{
+    geometry::Vertex,
+    material::{Material, MaterialId, Materials, Texture},
+    mesh::{generate_normals, triangulate, Mesh, MissingNormals, ObjOptions, ObjectData},
+};
+use anyhow::{bail, Context, Result};
+use glam::{Vec3A, Vec4};
+use std::{fs, path::Path, str::Lines};
+
+/// Names of the vertex properties holding texture coordinates, by convention.
+const TEXTURE_PROPERTIES: [[&str; 2]; 4] = [
+    ["u", "v"],
+    ["s", "t"],
+    ["texture_u", "texture_v"],
+    ["texture_s", "texture_t"],
+];
+
+/// Type of a number in a PLY file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    /// Returns the number of bytes taken by the type in binary files.
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Returns the value of full intensity for colors of this type.
+    fn full_color(self) -> f32 {
+        match self {
+            Self::I8 => i8::MAX.into(),
+            Self::U8 => u8::MAX.into(),
+            Self::I16 => i16::MAX.into(),
+            Self::U16 => u16::MAX.into(),
+            Self::I32 => i32::MAX as f32,
+            Self::U32 => u32::MAX as f32,
+            Self::F32 | Self::F64 => 1.0,
+        }
+    }
+
+    /// Reads a little-endian number of this type from the start of `bytes`,
+    /// which must be long enough.
+    fn read(self, bytes: &[u8]) -> f64 {
+        let bytes = &bytes[..self.size()];
+        match self {
+            Self::I8 => i8::from_le_bytes(bytes.try_into().unwrap()).into(),
+            Self::U8 => bytes[0].into(),
+            Self::I16 => i16::from_le_bytes(bytes.try_into().unwrap()).into(),
+            Self::U16 => u16::from_le_bytes(bytes.try_into().unwrap()).into(),
+            Self::I32 => i32::from_le_bytes(bytes.try_into().unwrap()).into(),
+            Self::U32 => u32::from_le_bytes(bytes.try_into().unwrap()).into(),
+            Self::F32 => f32::from_le_bytes(bytes.try_into().unwrap()).into(),
+            Self::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+}
+
+/// Type of a property of an element.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Scalar(Scalar),
+    /// A list of items, preceded by their number.
+    List {
+        count: Scalar,
+        item: Scalar,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: String,
+    kind: Kind,
+}
+
+/// A kind of element, such as vertices or faces, declared in the header.
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    /// Returns the index of the number property called `name`.
+    fn scalar(&self, name: &str) -> Option<(usize, Scalar)> {
+        self.properties
+            .iter()
+            .enumerate()
+            .find_map(|(i, property)| match property.kind {
+                Kind::Scalar(scalar) if property.name == name => Some((i, scalar)),
+                _ => None,
+            })
+    }
+
+    /// Returns the index of the first list property called one of `names`.
+    fn list(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|property| {
+            matches!(property.kind, Kind::List { .. }) && names.contains(&property.name.as_str())
+        })
+    }
+}
+
+/// The value of a property in one row of an element.
+#[derive(Clone, Debug)]
+enum Value {
+    Scalar(f64),
+    List(Vec<f64>),
+}
+
+impl Value {
+    /// Returns the number, or NaN for a list.
+    fn scalar(&self) -> f64 {
+        match self {
+            Self::Scalar(value) => *value,
+            Self::List(_) => f64::NAN,
+        }
+    }
+
+    /// Returns the items of a list, or nothing for a number.
+    fn list(&self) -> &[f64] {
+        match self {
+            Self::Scalar(_) => &[],
+            Self::List(values) => values,
+        }
+    }
+}
+
+/// The data after the header, read a row at a time.
+enum Body<'a> {
+    Ascii {
+        lines: std::iter::Enumerate<Lines<'a>>,
+        /// Line number of the first line after the header, minus one.
+        offset: usize,
+    },
+    Binary {
+        bytes: &'a [u8],
+        position: usize,
+    },
+}
+
+impl Body<'_> {
+    /// Reads the values of a row of `element` into `row`.
+    fn read_row(&mut self, element: &Element, path: &Path, row: &mut Vec<Value>) -> Result<()> {
+        row.clear();
+        match self {
+            Self::Ascii { lines, offset } => {
+                let Some((line_number, line)) = lines.find(|(_, line)| !line.trim().is_empty())
+                else {
+                    bail!(
+                        "In \"{}\": The file ends before all of its {} elements",
+                        path.display(),
+                        element.name
+                    );
+                };
+                let err = |message: &str| {
+                    format!(
+                        "In \"{}\" on line {}: {message}",
+                        path.display(),
+                        *offset + line_number + 1,
+                    )
+                };
+                let mut words = line.split_whitespace();
+                let mut number = || -> Result<f64> {
+                    let word = words
+                        .next()
+                        .with_context(|| err(&format!("Too few values for a {}", element.name)))?;
+                    word.parse()
+                        .with_context(|| err(&format!("Invalid number \"{word}\"")))
+                };
+                for property in &element.properties {
+                    row.push(match property.kind {
+                        Kind::Scalar(_) => Value::Scalar(number()?),
+                        Kind::List { .. } => {
+                            let count = number()? as usize;
+                            Value::List((0..count).map(|_| number()).collect::<Result<_>>()?)
+                        }
+                    });
+                }
+            }
+            Self::Binary { bytes, position } => {
+                let mut number = |scalar: Scalar| -> Result<f64> {
+                    let value = bytes
+                        .get(*position..*position + scalar.size())
+                        .map(|bytes| scalar.read(bytes))
+                        .with_context(|| {
+                            format!(
+                                "In \"{}\": The file ends before all of its {} elements",
+                                path.display(),
+                                element.name
+                            )
+                        })?;
+                    *position += scalar.size();
+                    Ok(value)
+                };
+                for property in &element.properties {
+                    row.push(match property.kind {
+                        Kind::Scalar(scalar) => Value::Scalar(number(scalar)?),
+                        Kind::List { count, item } => {
+                            let count = number(count)? as usize;
+                            Value::List((0..count).map(|_| number(item)).collect::<Result<_>>()?)
+                        }
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Loads a model from a PLY file, either ASCII or binary little-endian.
+///
+/// Vertices need `x`, `y` and `z` properties, and may have normals (`nx`,
+/// `ny`, `nz`), colors (`red`, `green`, `blue` and optionally `alpha`) and
+/// texture coordinates (`u` and `v`, or `s` and `t`). Faces list their
+/// vertices in `vertex_indices`, and may list texture coordinates for each
+/// corner in `texcoord`. Texture coordinates are used with the image named
+/// by a `comment TextureFile` line, if any. Colors are blended across each
+/// face and multiplied with the texture. Missing normals are smoothed across
+/// the whole model.
+pub fn load_ply_file(path: impl AsRef<Path>) -> Result<ObjectData> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("Failed to read \"{}\"", path.display()))?;
+
+    let header_end = bytes
+        .windows(b"end_header".len())
+        .position(|window| window == b"end_header")
+        .and_then(|start| {
+            let end = bytes[start..].iter().position(|&byte| byte == b'\n')?;
+            Some(start + end + 1)
+        })
+        .with_context(|| format!("In \"{}\": The header never ends", path.display()))?;
+    let header = String::from_utf8_lossy(&bytes[..header_end]);
+
+    let mut binary = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut texture_file = None;
+    for (line_number, line) in header.lines().enumerate() {
+        let err = |message: &str| {
+            format!(
+                "In \"{}\" on line {}: {message}",
+                path.display(),
+                line_number + 1,
+            )
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match *words.as_slice() {
+            ["ply"] if line_number == 0 => (),
+            _ if line_number == 0 => bail!(err("Not a PLY file")),
+            ["format", "ascii", _] => binary = Some(false),
+            ["format", "binary_little_endian", _] => binary = Some(true),
+            ["format", format, ..] => bail!(err(&format!("Unsupported format \"{format}\""))),
+            ["comment", "TextureFile", ref name @ ..] => texture_file = Some(name.join(" ")),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .with_context(|| err(&format!("Invalid element count \"{count}\"")))?,
+                properties: vec![],
+            }),
+            ["property", ..] => {
+                let element = elements
+                    .last_mut()
+                    .with_context(|| err("A property comes before any element"))?;
+                let scalar = |name: &str| {
+                    Scalar::from_name(name)
+                        .with_context(|| err(&format!("Unknown type \"{name}\"")))
+                };
+                let (kind, name) = match words[1..] {
+                    ["list", count, item, name] => (
+                        Kind::List {
+                            count: scalar(count)?,
+                            item: scalar(item)?,
+                        },
+                        name,
+                    ),
+                    [scalar_type, name] => (Kind::Scalar(scalar(scalar_type)?), name),
+                    _ => bail!(err("Invalid property")),
+                };
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            ["end_header"] => (),
+            ["comment" | "obj_info", ..] | [] => (),
+            [word, ..] => bail!(err(&format!("Unexpected \"{word}\""))),
+        }
+    }
+
+    let mut body = match binary {
+        Some(true) => Body::Binary {
+            bytes: &bytes[header_end..],
+            position: 0,
+        },
+        Some(false) => Body::Ascii {
+            lines: std::str::from_utf8(&bytes[header_end..])
+                .with_context(|| format!("In \"{}\": The file is not valid text", path.display()))?
+                .lines()
+                .enumerate(),
+            offset: header.lines().count(),
+        },
+        None => bail!("In \"{}\": The header has no format", path.display()),
+    };
+
+    let mut vertices = None;
+    let mut faces = None;
+    let mut row = vec![];
+    for element in &elements {
+        // The count is only trusted as far as there are rows to read
+        let mut rows = vec![];
+        for _ in 0..element.count {
+            body.read_row(element, path, &mut row)?;
+            rows.push(row.clone());
+        }
+        match element.name.as_str() {
+            "vertex" => vertices = Some((element, rows)),
+            "face" => faces = Some((element, rows)),
+            _ => (),
+        }
+    }
+
+    let Some((vertex, vertices)) = vertices else {
+        bail!("In \"{}\": There are no vertices", path.display());
+    };
+    let property = |name: &str| vertex.scalar(name);
+    let [Some(x), Some(y), Some(z)] = ["x", "y", "z"].map(|name| property(name).map(|(i, _)| i))
+    else {
+        bail!(
+            "In \"{}\": The vertices have no x, y and z properties",
+            path.display()
+        );
+    };
+    let normal = ["nx", "ny", "nz"].map(|name| property(name).map(|(i, _)| i));
+    let color = ["red", "green", "blue", "alpha"].map(property);
+    let texture = TEXTURE_PROPERTIES
+        .iter()
+        .find_map(|[u, v]| property(u).zip(property(v)).map(|((u, _), (v, _))| [u, v]));
+
+    let vertex = |row: &[Value]| {
+        let vector = |[x, y, z]: [usize; 3]| {
+            Vec3A::new(
+                row[x].scalar() as f32,
+                row[y].scalar() as f32,
+                row[z].scalar() as f32,
+            )
+        };
+        Vertex {
+            position: vector([x, y, z]),
+            normal: match normal {
+                [Some(x), Some(y), Some(z)] => vector([x, y, z]).normalize_or_zero(),
+                _ => Vec3A::ZERO,
+            },
+            texture: texture.map_or(Vec3A::ZERO, |[u, v]| {
+                Vec3A::new(row[u].scalar() as f32, row[v].scalar() as f32, 1.0)
+            }),
+            color: match color {
+                [Some(red), Some(green), Some(blue), alpha] => {
+                    let channel = |(i, scalar): (usize, Scalar)| {
+                        (row[i].scalar() as f32 / scalar.full_color()).clamp(0.0, 1.0)
+                    };
+                    Vec4::new(
+                        channel(red),
+                        channel(green),
+                        channel(blue),
+                        alpha.map_or(1.0, channel),
+                    )
+                }
+                _ => Vec4::ONE,
+            },
+        }
+    };
+    let has_normals = normal.iter().all(Option::is_some);
+    let vertices: Vec<Vertex> = vertices.iter().map(|row| vertex(row)).collect();
+
+    let mut triangles: Vec<([Vertex; 3], MaterialId)> = vec![];
+    let mut missing_normals = vec![];
+    let mut face_textures = false;
+    if let Some((face, faces)) = faces {
+        let indices = face
+            .list(&["vertex_indices", "vertex_index"])
+            .with_context(|| {
+                format!(
+                    "In \"{}\": The faces have no vertex_indices property",
+                    path.display()
+                )
+            })?;
+        let texcoord = face.list(&["texcoord"]);
+        face_textures = texcoord.is_some();
+        for (index, row) in faces.iter().enumerate() {
+            let corners = row[indices]
+                .list()
+                .iter()
+                .map(|&i| {
+                    if i >= 0.0 && (i as usize) < vertices.len() {
+                        Ok(i as usize)
+                    } else {
+                        bail!(
+                            "In \"{}\": Face {index} refers to vertex {i}, with {} vertices",
+                            path.display(),
+                            vertices.len()
+                        )
+                    }
+                })
+                .collect::<Result<Vec<usize>>>()?;
+            if corners.len() < 3 {
+                bail!(
+                    "In \"{}\": Face {index} has fewer than 3 vertices",
+                    path.display()
+                );
+            }
+            let textures = texcoord.map(|texcoord| row[texcoord].list());
+
+            let positions: Vec<Vec3A> = corners.iter().map(|&i| vertices[i].position).collect();
+            for triangle in triangulate(&positions) {
+                if !has_normals {
+                    missing_normals.push(MissingNormals {
+                        triangle: triangles.len(),
+                        positions: triangle.map(|corner| corners[corner]),
+                        missing: [true; 3],
+                        smoothing_group: 1,
+                    });
+                }
+                let triangle = triangle.map(|corner| {
+                    let mut vertex = vertices[corners[corner]];
+                    if let Some(&[u, v]) =
+                        textures.and_then(|textures| textures.get(2 * corner..2 * corner + 2))
+                    {
+                        vertex.texture = Vec3A::new(u as f32, v as f32, 1.0);
+                    }
+                    vertex
+                });
+                triangles.push((triangle, MaterialId::DEFAULT));
+            }
+        }
+    }
+
+    let mut materials = Materials::new();
+    if let (Some(name), true) = (texture_file, texture.is_some() || face_textures) {
+        let texture_path = path.with_file_name(&name);
+        let texture = Texture::try_from_path(&texture_path).with_context(|| {
+            format!(
+                "In \"{}\": Failed to load texture \"{}\"",
+                path.display(),
+                texture_path.display()
+            )
+        })?;
+        let id = materials.insert(&name, Material::from(texture));
+        for (_, material) in &mut triangles {
+            *material = id;
+        }
+    }
+
+    generate_normals(&mut triangles, &missing_normals, ObjOptions::default());
+
+    Ok(ObjectData {
+        mesh: Mesh::from_triangles(triangles),
+        materials,
+    })
+}
@@ -0,0 +1,566 @@
+//! Scenes of meshes placed by a hierarchy of transformed nodes, and loading
+//! them from TOML scene files.
+
+use crate::{
+    camera::Camera,
+    draw::Draw,
+    light::{Attenuation, Light, LightKind, Lighting},
+    material::Materials,
+    mesh::{load_model_file, Mesh, ObjectData},
+    pipeline::Pipeline,
+};
+use anyhow::{anyhow, Context, Result};
+use glam::{Mat4, Quat, Vec2, Vec3A};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    ops::{Index, IndexMut, Range},
+    path::Path,
+};
+use toml::Spanned;
+
+/// A translation, rotation and scale, applied in reverse order.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    /// Offset from the origin of the parent.
+    pub translation: Vec3A,
+    /// Orientation relative to the parent.
+    pub rotation: Quat,
+    /// Scale along each axis, before rotating.
+    pub scale: Vec3A,
+}
+
+impl Transform {
+    /// The transform leaving everything in place.
+    pub const IDENTITY: Self = Self {
+        translation: Vec3A::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3A::ONE,
+    };
+
+    /// Creates a transform moving everything by `translation`.
+    pub fn from_translation(translation: Vec3A) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Sets the rotation of the transform.
+    pub fn with_rotation(self, rotation: Quat) -> Self {
+        Self { rotation, ..self }
+    }
+
+    /// Sets the scale of the transform.
+    pub fn with_scale(self, scale: Vec3A) -> Self {
+        Self { scale, ..self }
+    }
+
+    /// Returns the matrix applying the transform.
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(
+            self.scale.into(),
+            self.rotation,
+            self.translation.into(),
+        )
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// A handle to a mesh in a [`Scene`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MeshId(pub u32);
+
+/// A handle to a node in a [`Scene`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub u32);
+
+/// A place in a [`Scene`], positioned relative to its parent, where a mesh
+/// may be drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    /// Transform from the node's space into its parent's, or into world
+    /// space for nodes without a parent.
+    pub transform: Transform,
+    /// Mesh drawn at the node, if any. Several nodes can share one mesh.
+    pub mesh: Option<MeshId>,
+    parent: Option<NodeId>,
+}
+
+impl Node {
+    /// Creates a node drawing `mesh`, if any, placed by `transform`.
+    pub fn new(mesh: Option<MeshId>, transform: Transform) -> Self {
+        Self {
+            transform,
+            mesh,
+            parent: None,
+        }
+    }
+
+    /// Returns the node this one is positioned relative to.
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+}
+
+/// Meshes, the materials they use, and the nodes placing them in the world.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    /// Meshes in model space, referred to by nodes.
+    pub meshes: Vec<Mesh>,
+    /// Materials referred to by every mesh.
+    pub materials: Materials,
+    /// Nodes, each after its parent, so that transforms can be combined in
+    /// a single pass.
+    nodes: Vec<Node>,
+}
+
+impl Scene {
+    /// Creates an empty scene.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the mesh of `object` without placing it anywhere, moving its
+    /// materials into the scene's.
+    pub fn add_object(&mut self, object: ObjectData) -> MeshId {
+        let ObjectData {
+            mut mesh,
+            materials,
+        } = object;
+        let offset = self.materials.append(materials);
+        for submesh in &mut mesh.submeshes {
+            submesh.material.0 += offset;
+        }
+        self.meshes.push(mesh);
+        MeshId(self.meshes.len() as u32 - 1)
+    }
+
+    /// Adds `node` as a child of `parent`, or at the root of the scene.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` is not a node of the scene.
+    pub fn add_node(&mut self, parent: Option<NodeId>, node: Node) -> NodeId {
+        assert!(
+            parent.is_none_or(|parent| (parent.0 as usize) < self.nodes.len()),
+            "Parent node {parent:?} does not exist"
+        );
+        self.nodes.push(Node { parent, ..node });
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+
+    /// Returns every node, each after its parent.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Writes the matrix transforming each node's space into world space,
+    /// combining the transforms of its ancestors, into `matrices`.
+    pub fn world_matrices(&self, matrices: &mut Vec<Mat4>) {
+        matrices.clear();
+        for node in &self.nodes {
+            let local = node.transform.matrix();
+            let matrix = match node.parent {
+                Some(parent) => matrices[parent.0 as usize] * local,
+                None => local,
+            };
+            matrices.push(matrix);
+        }
+    }
+}
+
+impl From<ObjectData> for Scene {
+    /// Creates a scene drawing `object` once, where it was modelled.
+    fn from(object: ObjectData) -> Self {
+        let mut scene = Self::new();
+        let mesh = scene.add_object(object);
+        scene.add_node(None, Node::new(Some(mesh), Transform::IDENTITY));
+        scene
+    }
+}
+
+impl Index<NodeId> for Scene {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+}
+
+impl IndexMut<NodeId> for Scene {
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0 as usize]
+    }
+}
+
+/// Where a camera starts and how much it sees.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CameraPose {
+    /// Position in world space.
+    pub position: Vec3A,
+    /// Pitch and yaw in radians.
+    pub rotation: Vec2,
+    /// Vertical field of view in radians.
+    pub fov: f32,
+}
+
+impl CameraPose {
+    /// Creates a camera in this pose.
+    pub fn camera(&self) -> Camera {
+        let mut camera = Camera::new(self.position, self.rotation);
+        camera.fov = self.fov;
+        camera
+    }
+}
+
+/// Render settings from a scene file, where `None` leaves the choice to the
+/// renderer.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RenderSettings {
+    /// Downscaling factor of the rendered image in the viewer.
+    pub scale: Option<u32>,
+    /// Whether to clip with a guard band, see
+    /// [`Pipeline::guard_band`](crate::Pipeline::guard_band).
+    pub guard_band: bool,
+    /// See [`Draw::alpha_cutoff`](crate::Draw::alpha_cutoff).
+    pub alpha_cutoff: Option<f32>,
+}
+
+impl RenderSettings {
+    /// Configures `pipeline` and `draw` to render with these settings. The
+    /// scale is left to the viewer, which sizes the frame.
+    pub fn apply(&self, pipeline: &mut Pipeline, draw: &mut Draw) {
+        pipeline.guard_band = self.guard_band;
+        if let Some(alpha_cutoff) = self.alpha_cutoff {
+            draw.alpha_cutoff = alpha_cutoff;
+        }
+    }
+}
+
+/// The contents of a scene file.
+#[derive(Clone, Debug)]
+pub struct SceneData {
+    /// Every model in the file, placed by its transform.
+    pub scene: Scene,
+    /// Ambient light and light sources, or the default lighting if the file
+    /// has neither.
+    pub lighting: Lighting,
+    /// Starting pose of the camera.
+    pub camera: Option<CameraPose>,
+    /// Color of the background.
+    pub clear_color: Option<[u8; 4]>,
+    /// How to render the scene.
+    pub settings: RenderSettings,
+}
+
+/// Either one value for every component of a vector, or each component.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Components {
+    Uniform(f32),
+    Each([f32; 3]),
+}
+
+impl From<Components> for Vec3A {
+    fn from(components: Components) -> Self {
+        match components {
+            Components::Uniform(value) => Vec3A::splat(value),
+            Components::Each(values) => Vec3A::from_array(values),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    models: Vec<ModelEntry>,
+    ambient_light: Option<Components>,
+    lights: Option<Vec<Spanned<LightEntry>>>,
+    camera: Option<CameraEntry>,
+    clear_color: Option<Spanned<Vec<u8>>>,
+    #[serde(default)]
+    settings: SettingsEntry,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelEntry {
+    name: Option<Spanned<String>>,
+    path: Option<Spanned<String>>,
+    group: Option<Spanned<String>>,
+    parent: Option<Spanned<String>>,
+    #[serde(default)]
+    translation: [f32; 3],
+    /// Degrees about the X, Y and Z axes, applied in that order.
+    #[serde(default)]
+    rotation: [f32; 3],
+    scale: Option<Components>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LightType {
+    Directional,
+    Point,
+    Spot,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightEntry {
+    #[serde(rename = "type")]
+    kind: LightType,
+    position: Option<[f32; 3]>,
+    direction: Option<[f32; 3]>,
+    color: Option<Components>,
+    #[serde(default = "one")]
+    intensity: f32,
+    /// Constant, linear and quadratic falloff.
+    attenuation: Option<[f32; 3]>,
+    /// Degrees.
+    inner_angle: Option<f32>,
+    /// Degrees.
+    outer_angle: Option<f32>,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraEntry {
+    #[serde(default)]
+    position: [f32; 3],
+    /// Pitch and yaw in degrees.
+    #[serde(default)]
+    rotation: [f32; 2],
+    /// Degrees.
+    #[serde(default = "default_fov")]
+    fov: f32,
+}
+
+fn default_fov() -> f32 {
+    90.0
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct SettingsEntry {
+    scale: Option<u32>,
+    #[serde(default)]
+    guard_band: bool,
+    alpha_cutoff: Option<f32>,
+}
+
+/// Loads a TOML scene file, along with every model it refers to.
+///
+/// Models are listed as `[[models]]` tables with a `path` to a model file
+/// of any format [`load_model_file`] reads, relative to the scene file, and
+/// optionally the name of a `group` of the file to use alone, a `name`, the
+/// `name` of an earlier model as their `parent`, and a `translation`,
+/// `rotation` in degrees about the X, Y and Z axes and `scale`. Models
+/// without a path only group their children. Each file is loaded once,
+/// however many models use it. The file can also set `ambient_light`,
+/// `[[lights]]`, the starting `[camera]`, the `clear_color` and `[settings]`
+/// for rendering.
+pub fn load_scene_file(path: impl AsRef<Path>) -> Result<SceneData> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read \"{}\"", path.display()))?;
+    let err = |span: Range<usize>, message: &str| {
+        let line_number = text[..span.start.min(text.len())].matches('\n').count() + 1;
+        format!("In \"{}\" on line {line_number}: {message}", path.display())
+    };
+
+    let file: SceneFile = toml::from_str(&text)
+        .map_err(|error| anyhow!(err(error.span().unwrap_or(0..0), error.message().trim())))?;
+
+    let mut scene = Scene::new();
+    let mut meshes: HashMap<String, MeshId> = HashMap::new();
+    let mut groups: HashMap<(String, String), MeshId> = HashMap::new();
+    let mut names: HashMap<String, NodeId> = HashMap::new();
+    for model in file.models {
+        let mesh = match model.path {
+            Some(model_path) => {
+                let mesh = match meshes.get(model_path.as_ref()) {
+                    Some(&mesh) => mesh,
+                    None => {
+                        let full_path = path.with_file_name(model_path.as_ref());
+                        let object = load_model_file(&full_path).with_context(|| {
+                            err(
+                                model_path.span(),
+                                &format!("Failed to load \"{}\"", full_path.display()),
+                            )
+                        })?;
+                        let mesh = scene.add_object(object);
+                        meshes.insert(model_path.as_ref().clone(), mesh);
+                        mesh
+                    }
+                };
+                match model.group {
+                    Some(group) => {
+                        let key = (model_path.into_inner(), group.as_ref().clone());
+                        let part = match groups.get(&key) {
+                            Some(&part) => part,
+                            None => {
+                                let full = &scene.meshes[mesh.0 as usize];
+                                let triangles = full
+                                    .group(group.as_ref())
+                                    .with_context(|| {
+                                        err(
+                                            group.span(),
+                                            &format!(
+                                                "No group named \"{}\" in \"{}\"",
+                                                key.1, key.0
+                                            ),
+                                        )
+                                    })?
+                                    .triangles
+                                    .clone();
+                                scene.meshes.push(full.extract(triangles));
+                                let part = MeshId(scene.meshes.len() as u32 - 1);
+                                groups.insert(key, part);
+                                part
+                            }
+                        };
+                        Some(part)
+                    }
+                    None => Some(mesh),
+                }
+            }
+            None => {
+                if let Some(group) = model.group {
+                    return Err(anyhow!(err(
+                        group.span(),
+                        "A group needs a path to take it from"
+                    )));
+                }
+                None
+            }
+        };
+
+        let parent = match model.parent {
+            Some(parent) => Some(*names.get(parent.as_ref()).with_context(|| {
+                err(
+                    parent.span(),
+                    &format!("No model named \"{}\" before this one", parent.as_ref()),
+                )
+            })?),
+            None => None,
+        };
+
+        let [x, y, z] = model.rotation.map(f32::to_radians);
+        let transform = Transform {
+            translation: Vec3A::from_array(model.translation),
+            rotation: Quat::from_rotation_z(z)
+                * Quat::from_rotation_y(y)
+                * Quat::from_rotation_x(x),
+            scale: model.scale.map_or(Vec3A::ONE, Vec3A::from),
+        };
+        let node = scene.add_node(parent, Node::new(mesh, transform));
+
+        if let Some(name) = model.name {
+            if names.contains_key(name.as_ref()) {
+                return Err(anyhow!(err(
+                    name.span(),
+                    &format!("There is already a model named \"{}\"", name.as_ref())
+                )));
+            }
+            names.insert(name.into_inner(), node);
+        }
+    }
+
+    let mut lighting = Lighting::default();
+    if let Some(ambient) = file.ambient_light {
+        lighting.ambient = ambient.into();
+    }
+    if let Some(lights) = file.lights {
+        lighting.lights = lights
+            .into_iter()
+            .map(|light| {
+                let span = light.span();
+                let light = light.into_inner();
+                let required = |value: Option<[f32; 3]>, name: &str| {
+                    value
+                        .map(Vec3A::from_array)
+                        .with_context(|| err(span.clone(), &format!("Light has no {name}")))
+                };
+                let attenuation = light.attenuation.map_or(
+                    Attenuation::default(),
+                    |[constant, linear, quadratic]| Attenuation {
+                        constant,
+                        linear,
+                        quadratic,
+                    },
+                );
+                let kind = match light.kind {
+                    LightType::Directional => LightKind::Directional {
+                        direction: required(light.direction, "direction")?.normalize_or_zero(),
+                    },
+                    LightType::Point => LightKind::Point {
+                        position: required(light.position, "position")?,
+                        attenuation,
+                    },
+                    LightType::Spot => {
+                        let outer_angle = light
+                            .outer_angle
+                            .with_context(|| err(span.clone(), "Light has no outer_angle"))?;
+                        LightKind::Spot {
+                            position: required(light.position, "position")?,
+                            direction: required(light.direction, "direction")?.normalize_or_zero(),
+                            attenuation,
+                            inner_angle: light.inner_angle.unwrap_or(outer_angle).to_radians(),
+                            outer_angle: outer_angle.to_radians(),
+                        }
+                    }
+                };
+                Ok(Light {
+                    kind,
+                    color: light.color.map_or(Vec3A::ONE, Vec3A::from),
+                    intensity: light.intensity,
+                })
+            })
+            .collect::<Result<_>>()?;
+    }
+
+    let camera = file.camera.map(|camera| CameraPose {
+        position: Vec3A::from_array(camera.position),
+        rotation: Vec2::from_array(camera.rotation.map(f32::to_radians)),
+        fov: camera.fov.to_radians(),
+    });
+
+    let clear_color = match file.clear_color {
+        Some(color) => Some(match *color.as_ref().as_slice() {
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => {
+                return Err(anyhow!(err(
+                    color.span(),
+                    "Colors must have three or four components"
+                )))
+            }
+        }),
+        None => None,
+    };
+
+    Ok(SceneData {
+        scene,
+        lighting,
+        camera,
+        clear_color,
+        settings: RenderSettings {
+            scale: file.settings.scale,
+            guard_band: file.settings.guard_band,
+            alpha_cutoff: file.settings.alpha_cutoff,
+        },
+    })
+}
@@ -0,0 +1,159 @@
+//! Loading models from STL files.
+
+use crate::{
+    geometry::Vertex,
+    material::{MaterialId, Materials},
+    mesh::{triangulate, Mesh, ObjectData},
+};
+use anyhow::{bail, Context, Result};
+use glam::{Vec3A, Vec4};
+use std::{fs, path::Path};
+
+/// Size of the header of a binary STL file, before the number of triangles.
+const HEADER_SIZE: usize = 80;
+/// Size of each triangle of a binary STL file: a normal, three corners and
+/// two bytes of attributes.
+const TRIANGLE_SIZE: usize = 50;
+
+/// A triangle as read from the file, with the normal given for it and the
+/// group it belongs to.
+type Facet = ([Vec3A; 3], Vec3A, Option<usize>);
+
+/// Returns whether `bytes`, the start of a file `length` bytes long, has the
+/// size of a binary STL file for the number of triangles in its header.
+/// ASCII files cannot be told apart by their start alone, as binary headers
+/// may also start with `solid`.
+pub(crate) fn is_binary_stl(bytes: &[u8], length: u64) -> bool {
+    bytes
+        .get(HEADER_SIZE..HEADER_SIZE + 4)
+        .is_some_and(|count| {
+            let count = u32::from_le_bytes(count.try_into().unwrap());
+            length == (HEADER_SIZE + 4) as u64 + u64::from(count) * TRIANGLE_SIZE as u64
+        })
+}
+
+/// Loads a model from an STL file, either ASCII or binary, with flat normals
+/// facing the side the corners are counterclockwise from. Solids of ASCII
+/// files with names become groups of those names.
+pub fn load_stl_file(path: impl AsRef<Path>) -> Result<ObjectData> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("Failed to read \"{}\"", path.display()))?;
+
+    let (facets, names) = if is_binary_stl(&bytes, bytes.len() as u64) {
+        (read_binary(&bytes), vec![])
+    } else if bytes.starts_with(b"solid") {
+        let text = std::str::from_utf8(&bytes)
+            .with_context(|| format!("In \"{}\": The file is not valid text", path.display()))?;
+        read_ascii(text, path)?
+    } else {
+        bail!(
+            "In \"{}\": The file neither starts with \"solid\" nor has the size of a binary STL file",
+            path.display()
+        );
+    };
+
+    let triangles = facets.into_iter().map(|(corners, normal, group)| {
+        let [a, b, c] = corners;
+        // Normals in files are often missing or wrong, so they are a fallback
+        let normal = (b - a)
+            .cross(c - a)
+            .try_normalize()
+            .or(normal.try_normalize())
+            .unwrap_or(Vec3A::Y);
+        let vertices = corners.map(|position| Vertex {
+            position,
+            normal,
+            texture: Vec3A::ZERO,
+            color: Vec4::ONE,
+        });
+        (vertices, MaterialId::DEFAULT, group)
+    });
+    Ok(ObjectData {
+        mesh: Mesh::from_grouped_triangles(triangles, &names),
+        materials: Materials::new(),
+    })
+}
+
+/// Reads the triangles of a binary STL file, whose size has been checked.
+fn read_binary(bytes: &[u8]) -> Vec<Facet> {
+    let vector = |bytes: &[u8]| {
+        Vec3A::from_array(std::array::from_fn(|i| {
+            f32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap())
+        }))
+    };
+    bytes[HEADER_SIZE + 4..]
+        .chunks_exact(TRIANGLE_SIZE)
+        .map(|triangle| {
+            let corners = [12, 24, 36].map(|offset| vector(&triangle[offset..]));
+            (corners, vector(triangle), None)
+        })
+        .collect()
+}
+
+/// Reads the triangles of an ASCII STL file, along with the names of its
+/// solids. Facets of more than three corners are split into triangles.
+fn read_ascii(text: &str, path: &Path) -> Result<(Vec<Facet>, Vec<String>)> {
+    let mut facets = vec![];
+    let mut names: Vec<String> = vec![];
+    let mut group = None;
+    let mut normal = Vec3A::ZERO;
+    let mut corners: Vec<Vec3A> = vec![];
+
+    for (line_number, line) in text.lines().enumerate() {
+        let err = |message: &str| {
+            format!(
+                "In \"{}\" on line {}: {message}",
+                path.display(),
+                line_number + 1,
+            )
+        };
+        let vector = |words: &mut dyn Iterator<Item = &str>| -> Result<Vec3A> {
+            let numbers: Vec<f32> = words
+                .map(|word| {
+                    word.parse()
+                        .with_context(|| err(&format!("Invalid number \"{word}\"")))
+                })
+                .collect::<Result<_>>()?;
+            match *numbers.as_slice() {
+                [x, y, z] => Ok(Vec3A::new(x, y, z)),
+                _ => bail!(err("Expected 3 numbers")),
+            }
+        };
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("solid") => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                group = (!name.is_empty()).then(|| {
+                    names
+                        .iter()
+                        .position(|group| *group == name)
+                        .unwrap_or_else(|| {
+                            names.push(name);
+                            names.len() - 1
+                        })
+                });
+            }
+            Some("facet") => {
+                if words.next() != Some("normal") {
+                    bail!(err("Expected \"normal\" after \"facet\""));
+                }
+                normal = vector(&mut words)?;
+                corners.clear();
+            }
+            Some("vertex") => corners.push(vector(&mut words)?),
+            Some("endfacet") => {
+                if corners.len() < 3 {
+                    bail!(err("Facets must have at least 3 corners"));
+                }
+                for triangle in triangulate(&corners) {
+                    facets.push((triangle.map(|i| corners[i]), normal, group));
+                }
+            }
+            Some("endsolid") => group = None,
+            Some("outer" | "endloop") | None => (),
+            Some(word) => bail!(err(&format!("Unexpected \"{word}\""))),
+        }
+    }
+    Ok((facets, names))
+}
@@ -0,0 +1,362 @@
+//! Renders the bundled models from fixed camera poses and compares the
+//! results against the reference images in `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the current output as the new
+//! reference images after an intentional change to the renderer. On a
+//! mismatch, the actual output and a diff image are written to
+//! `target/golden-diffs`.
+
+use drawing::{
+    headless, load_model_file, load_scene_file, Camera, Lighting, Node, RenderSettings, Scene,
+    Transform,
+};
+use glam::{Quat, UVec2, Vec2, Vec3A};
+use image::{Rgba, RgbaImage};
+use std::{env, fs, path::PathBuf};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+const CLEAR_COLOR: [u8; 4] = [110, 177, 255, 255];
+/// Largest difference allowed in any channel of a pixel before it counts as mismatched.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Largest fraction of mismatched pixels allowed, to absorb floating point
+/// differences along triangle edges between platforms.
+const MAX_MISMATCHED_FRACTION: f32 = 0.002;
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"))
+}
+
+fn diff_directory() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden-diffs")
+}
+
+fn is_mismatched(a: &Rgba<u8>, b: &Rgba<u8>) -> bool {
+    a.0.iter()
+        .zip(b.0)
+        .any(|(&a, b)| a.abs_diff(b) > CHANNEL_TOLERANCE)
+}
+
+/// Highlights mismatched pixels in red over a dimmed copy of the expected image.
+fn diff_image(expected: &RgbaImage, actual: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let (expected, actual) = (expected.get_pixel(x, y), actual.get_pixel(x, y));
+        if is_mismatched(expected, actual) {
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.0.map(|channel| channel / 3);
+            Rgba([r, g, b, 255])
+        }
+    })
+}
+
+fn load_scene(model: &str) -> Scene {
+    let object = load_model_file(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(model))
+        .unwrap_or_else(|error| panic!("Failed to load {model}: {error:#}"));
+    Scene::from(object)
+}
+
+fn assert_golden(name: &str, model: &str, position: Vec3A, rotation_degrees: Vec2) {
+    assert_golden_scene(name, &load_scene(model), position, rotation_degrees);
+}
+
+fn assert_golden_scene(name: &str, scene: &Scene, position: Vec3A, rotation_degrees: Vec2) {
+    let rotation = Vec2::new(
+        rotation_degrees.x.to_radians(),
+        rotation_degrees.y.to_radians(),
+    );
+    let camera = Camera::new(position, rotation);
+    let actual = headless::render_to_image(
+        scene,
+        &camera,
+        &Lighting::default(),
+        &RenderSettings::default(),
+        UVec2::new(WIDTH, HEIGHT),
+        CLEAR_COLOR,
+    );
+    assert_golden_image(name, &actual);
+}
+
+fn assert_golden_image(name: &str, actual: &RgbaImage) {
+    let path = golden_path(name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let expected = image::open(&path)
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to open reference image \"{}\": {error}. Run with UPDATE_GOLDEN=1 to create it.",
+                path.display()
+            )
+        })
+        .to_rgba8();
+    assert_eq!(
+        expected.dimensions(),
+        actual.dimensions(),
+        "Reference image \"{}\" has the wrong size",
+        path.display()
+    );
+
+    let mismatched = expected
+        .pixels()
+        .zip(actual.pixels())
+        .filter(|(expected, actual)| is_mismatched(expected, actual))
+        .count();
+    let allowed = (MAX_MISMATCHED_FRACTION * (WIDTH * HEIGHT) as f32) as usize;
+    if mismatched > allowed {
+        let directory = diff_directory();
+        fs::create_dir_all(&directory).unwrap();
+        let actual_path = directory.join(format!("{name}_actual.png"));
+        let diff_path = directory.join(format!("{name}_diff.png"));
+        actual.save(&actual_path).unwrap();
+        diff_image(&expected, actual).save(&diff_path).unwrap();
+        panic!(
+            "{mismatched} pixels differ from \"{}\" (at most {allowed} allowed).\nActual output: \"{}\"\nDiff: \"{}\"",
+            path.display(),
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+#[test]
+fn teapot_front() {
+    assert_golden(
+        "teapot_front",
+        "assets/teapot/teapot.obj",
+        Vec3A::new(0.0, 2.0, -6.0),
+        Vec2::new(-10.0, 180.0),
+    );
+}
+
+#[test]
+fn grass_block_corner() {
+    assert_golden(
+        "grass_block_corner",
+        "assets/grass_block/grass_block.obj",
+        Vec3A::new(2.5, 2.5, -2.5),
+        Vec2::new(-35.0, 225.0),
+    );
+}
+
+#[test]
+fn castle_entrance() {
+    assert_golden(
+        "castle_entrance",
+        "assets/castle/castle.obj",
+        Vec3A::new(0.0, 3.0, -4.0),
+        Vec2::new(0.0, 180.0),
+    );
+}
+
+#[test]
+fn castle_aerial() {
+    assert_golden(
+        "castle_aerial",
+        "assets/castle/castle.obj",
+        Vec3A::new(-6.0, 7.0, -8.5),
+        Vec2::new(-35.0, 145.0),
+    );
+}
+
+#[test]
+fn transparent_panes() {
+    assert_golden(
+        "transparent_panes",
+        "tests/assets/panes/panes.obj",
+        Vec3A::new(0.0, 0.0, -4.0),
+        Vec2::new(0.0, 180.0),
+    );
+}
+
+#[test]
+fn specular_sphere() {
+    assert_golden(
+        "specular_sphere",
+        "tests/assets/sphere/sphere.obj",
+        Vec3A::new(0.0, 0.0, -3.0),
+        Vec2::new(0.0, 180.0),
+    );
+}
+
+#[test]
+fn grazing_quad_normals() {
+    assert_golden(
+        "grazing_quad_normals",
+        "tests/assets/quad/quad.obj",
+        Vec3A::new(0.0, 1.0, -3.0),
+        Vec2::new(-15.0, 180.0),
+    );
+}
+
+#[test]
+fn concave_polygons() {
+    assert_golden(
+        "concave_polygons",
+        "tests/assets/polygons/polygons.obj",
+        Vec3A::new(0.0, 0.0, -3.5),
+        Vec2::new(0.0, 180.0),
+    );
+}
+
+#[test]
+fn face_index_forms() {
+    assert_golden(
+        "face_index_forms",
+        "tests/assets/indices/indices.obj",
+        Vec3A::new(0.0, 0.0, -4.0),
+        Vec2::new(0.0, 180.0),
+    );
+}
+
+#[test]
+fn gltf_separate_files() {
+    assert_golden(
+        "gltf_blocks",
+        "tests/assets/gltf/blocks.gltf",
+        Vec3A::new(0.0, 1.5, -3.0),
+        Vec2::new(-20.0, 180.0),
+    );
+}
+
+#[test]
+fn gltf_binary() {
+    assert_golden(
+        "gltf_blocks",
+        "tests/assets/gltf/blocks.glb",
+        Vec3A::new(0.0, 1.5, -3.0),
+        Vec2::new(-20.0, 180.0),
+    );
+}
+
+#[test]
+fn stl_ascii() {
+    assert_golden(
+        "stl_house",
+        "tests/assets/stl/house.stl",
+        Vec3A::new(2.0, 2.5, -3.5),
+        Vec2::new(-25.0, 210.0),
+    );
+}
+
+#[test]
+fn stl_binary() {
+    assert_golden(
+        "stl_house",
+        "tests/assets/stl/house_binary.stl",
+        Vec3A::new(2.0, 2.5, -3.5),
+        Vec2::new(-25.0, 210.0),
+    );
+}
+
+#[test]
+fn ply_ascii() {
+    assert_golden(
+        "ply_pyramid",
+        "tests/assets/ply/pyramid.ply",
+        Vec3A::new(1.5, 2.5, -3.0),
+        Vec2::new(-30.0, 205.0),
+    );
+}
+
+#[test]
+fn ply_binary() {
+    assert_golden(
+        "ply_pyramid",
+        "tests/assets/ply/pyramid_binary.ply",
+        Vec3A::new(1.5, 2.5, -3.0),
+        Vec2::new(-30.0, 205.0),
+    );
+}
+
+#[test]
+fn ply_textured() {
+    assert_golden(
+        "ply_textured",
+        "tests/assets/ply/textured.ply",
+        Vec3A::new(0.0, 0.0, -3.0),
+        Vec2::new(0.0, 180.0),
+    );
+}
+
+#[test]
+fn grass_block_instances() {
+    let mut scene = load_scene("assets/grass_block/grass_block.obj");
+    let mesh = scene.nodes()[0].mesh;
+    let row = scene.add_node(
+        None,
+        Node::new(
+            None,
+            Transform::from_translation(Vec3A::new(0.0, 0.0, 4.0))
+                .with_rotation(Quat::from_rotation_y(30f32.to_radians())),
+        ),
+    );
+    scene.add_node(
+        Some(row),
+        Node::new(
+            mesh,
+            Transform::from_translation(Vec3A::new(-3.0, 0.0, 0.0)),
+        ),
+    );
+    let stretched = scene.add_node(
+        Some(row),
+        Node::new(
+            mesh,
+            Transform::from_translation(Vec3A::new(3.0, 0.5, 0.0))
+                .with_scale(Vec3A::new(1.0, 1.5, 1.0)),
+        ),
+    );
+    scene.add_node(
+        Some(stretched),
+        Node::new(
+            mesh,
+            Transform::from_translation(Vec3A::new(0.0, 2.0, 0.0))
+                .with_rotation(Quat::from_rotation_y(45f32.to_radians()))
+                .with_scale(Vec3A::splat(0.5)),
+        ),
+    );
+    assert_golden_scene(
+        "grass_block_instances",
+        &scene,
+        Vec3A::new(0.0, 4.0, -5.0),
+        Vec2::new(-25.0, 180.0),
+    );
+}
+
+#[test]
+fn meadow_scene_file() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/meadow.toml");
+    let data = load_scene_file(&path)
+        .unwrap_or_else(|error| panic!("Failed to load {}: {error:#}", path.display()));
+    let camera = data.camera.expect("The scene file should set the camera");
+    let actual = headless::render_to_image(
+        &data.scene,
+        &camera.camera(),
+        &data.lighting,
+        &data.settings,
+        UVec2::new(WIDTH, HEIGHT),
+        data.clear_color.unwrap_or(CLEAR_COLOR),
+    );
+    assert_golden_image("meadow_scene_file", &actual);
+}
+
+#[test]
+fn polygon_groups_scene_file() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/assets/polygons/parts.toml");
+    let data = load_scene_file(&path)
+        .unwrap_or_else(|error| panic!("Failed to load {}: {error:#}", path.display()));
+    let camera = data.camera.expect("The scene file should set the camera");
+    let actual = headless::render_to_image(
+        &data.scene,
+        &camera.camera(),
+        &data.lighting,
+        &data.settings,
+        UVec2::new(WIDTH, HEIGHT),
+        CLEAR_COLOR,
+    );
+    assert_golden_image("polygon_groups_scene_file", &actual);
+}
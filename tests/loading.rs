@@ -0,0 +1,303 @@
+//! Loads small models, mostly written on the fly, checking what the loaders
+//! make of them and the errors they report for broken files.
+
+use drawing::{
+    load_from_obj_file, load_from_obj_file_with_options, load_gltf_file, load_model_file,
+    load_scene_file, load_stl_file, Material, ObjOptions,
+};
+use glam::Vec3A;
+use std::{f32::consts::FRAC_1_SQRT_2, fs, path::PathBuf};
+
+/// Writes `contents` to a file called `name` in a scratch directory and
+/// returns its path. Each test uses its own names, as tests run in parallel.
+fn write_file(name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn obj_smoothing_group_on() {
+    // Two faces at a right angle, meeting along the edge from 1 to 2
+    let path = write_file(
+        "smoothing_on.obj",
+        "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\ns on\nf 1 2 3\nf 1 4 2\n",
+    );
+    let options = ObjOptions {
+        smooth_by_default: false,
+        ..ObjOptions::default()
+    };
+    let object = load_from_obj_file_with_options(&path, options).unwrap();
+    let shared_normal = Vec3A::new(0.0, FRAC_1_SQRT_2, FRAC_1_SQRT_2);
+    let smoothed = object
+        .mesh
+        .vertices
+        .iter()
+        .filter(|vertex| vertex.normal.abs_diff_eq(shared_normal, 1e-5))
+        .count();
+    assert_eq!(smoothed, 2, "{:?}", object.mesh.vertices);
+}
+
+/// Loads the scene file `contents` and returns its error, checking that the
+/// error names the file and `line`.
+fn scene_error(name: &str, contents: &str, line: usize) -> String {
+    let path = write_file(name, contents);
+    let error = format!("{:#}", load_scene_file(&path).unwrap_err());
+    let location = format!("In \"{}\" on line {line}:", path.display());
+    assert!(error.starts_with(&location), "{error}");
+    error
+}
+
+#[test]
+fn scene_unknown_key() {
+    let error = scene_error(
+        "unknown_key.toml",
+        "[camera]\nposition = [0, 1, 2]\nzoom = 3\n",
+        3,
+    );
+    assert!(error.contains("zoom"), "{error}");
+}
+
+#[test]
+fn scene_bad_vector_length() {
+    scene_error(
+        "bad_vector_length.toml",
+        "[[models]]\nname = \"empty\"\ntranslation = [1, 2]\n",
+        3,
+    );
+}
+
+#[test]
+fn scene_unknown_parent() {
+    let error = scene_error(
+        "unknown_parent.toml",
+        "[[models]]\nname = \"first\"\n\n[[models]]\nparent = \"second\"\n",
+        5,
+    );
+    assert!(error.contains("No model named \"second\""), "{error}");
+}
+
+#[test]
+fn obj_invalid_faces() {
+    let cases = [
+        (
+            "f 0 1 2",
+            "The position index 0 is out of range, with 3 defined so far",
+        ),
+        (
+            "f 1 2 99",
+            "The position index 99 is out of range, with 3 defined so far",
+        ),
+        (
+            "f -99 1 2",
+            "The position index -99 is out of range, with 3 defined so far",
+        ),
+        ("f 1/2/3/4 2 3", "Invalid face vertex \"1/2/3/4\""),
+    ];
+    for (i, (face, message)) in cases.into_iter().enumerate() {
+        let path = write_file(
+            &format!("invalid_face_{i}.obj"),
+            format!("v 0 0 0\nv 1 0 0\nv 0 1 0\n{face}\n"),
+        );
+        let Err(error) = load_from_obj_file(&path) else {
+            panic!("\"{face}\" should not load");
+        };
+        let error = format!("{error:#}");
+        let expected = format!("In \"{}\" on line 4: {message}", path.display());
+        assert!(error.contains(&expected), "{face}: {error}");
+    }
+}
+
+#[test]
+fn obj_group_ranges() {
+    let path = write_file(
+        "groups.obj",
+        "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+         f 1 2 3\n\
+         o base\nf 1 2 3 4\n\
+         g lid\nf 2 3 4\n\
+         o base\nf 1 3 4\n\
+         g\nf 1 2 4\n",
+    );
+    let Ok(object) = load_from_obj_file(&path) else {
+        panic!("Failed to load {}", path.display());
+    };
+    let mesh = &object.mesh;
+
+    // Triangles outside of every group come first, then each group's
+    // triangles together, even where its faces were split up in the file
+    let names: Vec<_> = mesh
+        .groups
+        .iter()
+        .map(|group| group.name.as_str())
+        .collect();
+    assert_eq!(names, ["base", "lid"]);
+    assert_eq!(mesh.group("base").unwrap().triangles, 2..5);
+    assert_eq!(mesh.group("lid").unwrap().triangles, 5..6);
+    assert_eq!(mesh.group("missing"), None);
+
+    let lid = mesh.indices[5].map(|i| mesh.vertices[i as usize].position);
+    assert_eq!(
+        lid,
+        [
+            Vec3A::new(1.0, 0.0, 0.0),
+            Vec3A::new(1.0, 1.0, 0.0),
+            Vec3A::new(0.0, 1.0, 0.0),
+        ]
+    );
+}
+
+/// A glTF file with a single triangle, of a material with `material`'s
+/// properties.
+fn gltf_triangle(material: &str) -> String {
+    format!(
+        r#"{{
+  "asset": {{ "version": "2.0" }},
+  "scene": 0,
+  "scenes": [{{ "nodes": [0] }}],
+  "nodes": [{{ "mesh": 0 }}],
+  "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "material": 0 }}] }}],
+  "materials": [{{ {material} }}],
+  "accessors": [{{
+    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+    "min": [0, 0, 0], "max": [1, 1, 0]
+  }}],
+  "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+  "buffers": [{{
+    "byteLength": 36,
+    "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA"
+  }}]
+}}"#
+    )
+}
+
+#[test]
+fn gltf_alpha_modes() {
+    let cases = [
+        (
+            r#""alphaMode": "MASK", "alphaCutoff": 0.25"#,
+            Some(0.25),
+            false,
+        ),
+        (r#""alphaMode": "MASK""#, Some(0.5), false),
+        (r#""alphaMode": "BLEND""#, None, true),
+        (r#""alphaMode": "OPAQUE""#, None, false),
+    ];
+    for (i, (mode, alpha_cutoff, translucent)) in cases.into_iter().enumerate() {
+        let material =
+            format!(r#""pbrMetallicRoughness": {{ "baseColorFactor": [1, 1, 1, 0.4] }}, {mode}"#);
+        let path = write_file(&format!("alpha_mode_{i}.gltf"), gltf_triangle(&material));
+        let object = load_gltf_file(&path)
+            .unwrap_or_else(|error| panic!("Failed to load {mode}: {error:#}"));
+        let material = &object.materials[object.mesh.submeshes[0].material];
+        assert_eq!(material.alpha_cutoff, alpha_cutoff, "{mode}");
+        assert_eq!(material.is_translucent(), translucent, "{mode}");
+    }
+}
+
+/// Returns the path of a file in `tests/assets`.
+fn asset(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/assets")
+        .join(name)
+}
+
+#[test]
+fn model_format_detected_from_contents() {
+    let obj = write_file("detected.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n");
+    let models = [
+        obj,
+        asset("gltf/blocks.glb"),
+        asset("stl/house.stl"),
+        asset("stl/house_binary.stl"),
+        asset("ply/pyramid.ply"),
+        asset("ply/pyramid_binary.ply"),
+    ];
+    for path in models {
+        let Ok(expected) = load_model_file(&path) else {
+            panic!("Failed to load {}", path.display());
+        };
+        let bytes = fs::read(&path).unwrap();
+        let stem = path.file_stem().unwrap().to_str().unwrap();
+        // A wrong extension and none at all
+        for name in [format!("{stem}_detected.dat"), format!("{stem}_detected")] {
+            let copy = write_file(&name, &bytes);
+            let object = load_model_file(&copy)
+                .unwrap_or_else(|error| panic!("Failed to load {name}: {error:#}"));
+            assert_eq!(object.mesh.indices, expected.mesh.indices, "{name}");
+            assert_eq!(object.mesh.vertices, expected.mesh.vertices, "{name}");
+        }
+    }
+}
+
+#[test]
+fn binary_stl_starting_with_solid() {
+    // Exporters often start the header of binary files with "solid" too
+    let path = asset("stl/house_binary.stl");
+    let bytes = fs::read(&path).unwrap();
+    assert!(bytes.starts_with(b"solid"));
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap());
+    let object = load_stl_file(&path)
+        .unwrap_or_else(|error| panic!("Failed to load {}: {error:#}", path.display()));
+    assert_eq!(object.mesh.indices.len(), count as usize);
+}
+
+#[test]
+fn stl_neither_ascii_nor_binary() {
+    let path = write_file(
+        "garbage.stl",
+        "Not a model at all, but long enough to have a size.",
+    );
+    let Err(error) = load_stl_file(&path) else {
+        panic!("The garbage should not load");
+    };
+    assert_eq!(
+        format!("{error:#}"),
+        format!(
+            "In \"{}\": The file neither starts with \"solid\" nor has the size of a binary STL file",
+            path.display()
+        )
+    );
+}
+
+#[test]
+fn mtl_unsupported_colors_and_halo() {
+    write_file(
+        "unsupported.mtl",
+        "newmtl glow\n\
+         Ka spectral file.rfl 1.0\n\
+         Kd xyz 0.5 0.5 0.5\n\
+         Ks spectral ident.rfl\n\
+         d -halo 0.5\n",
+    );
+    let path = write_file(
+        "unsupported_mtl.obj",
+        "mtllib unsupported.mtl\nusemtl glow\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+    );
+    let Ok(object) = load_from_obj_file(&path) else {
+        panic!("Failed to load {}", path.display());
+    };
+
+    // Colors that are not RGB keep their defaults
+    let material = object.materials.get("glow").unwrap();
+    assert_eq!(material.ambient, Material::default().ambient);
+    assert_eq!(material.diffuse, Material::default().diffuse);
+    assert_eq!(material.specular, Material::default().specular);
+    assert_eq!(material.dissolve, 0.5);
+}
+
+#[test]
+fn gltf_accessor_out_of_range() {
+    let path = write_file(
+        "accessor_out_of_range.gltf",
+        r#"{"asset":{"version":"2.0"},"meshes":[{"primitives":[{"attributes":{"POSITION":5}}]}],"nodes":[{"mesh":0}],"scenes":[{"nodes":[0]}]}"#,
+    );
+    let Err(error) = load_gltf_file(&path) else {
+        panic!("The missing accessor should not load");
+    };
+    let error = format!("{error:#}");
+    assert!(
+        error.starts_with(&format!("In \"{}\"", path.display())),
+        "{error}"
+    );
+}
@@ -0,0 +1,270 @@
+//! Checks the work the pipeline stages do on small scenes, through the
+//! statistics they keep and a few chosen pixels, rather than through whole
+//! reference images.
+
+use drawing::{
+    headless, load_model_file, Bvh, Camera, Draw, DrawStats, Frustum, Lighting, Material,
+    MaterialId, Materials, Pipeline, RenderSettings, Scene, ScreenTriangle, ScreenVertex, Vertex,
+};
+use glam::{UVec2, Vec2, Vec3A, Vec4};
+use std::{f32::consts::PI, path::PathBuf};
+
+const WIDTH: usize = 160;
+const HEIGHT: usize = 120;
+
+fn load_scene(model: &str) -> Scene {
+    let object = load_model_file(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(model))
+        .unwrap_or_else(|error| panic!("Failed to load {model}: {error:#}"));
+    Scene::from(object)
+}
+
+/// Renders `scene` through `pipeline` from `position` looking at
+/// `rotation_degrees`, returning the frame and the rasterizer.
+fn render(
+    pipeline: &mut Pipeline,
+    scene: &Scene,
+    position: Vec3A,
+    rotation_degrees: Vec2,
+) -> (Vec<u8>, Draw) {
+    let mut camera = Camera::new(
+        position,
+        Vec2::new(
+            rotation_degrees.x.to_radians(),
+            rotation_degrees.y.to_radians(),
+        ),
+    );
+    camera.aspect_ratio = WIDTH as f32 / HEIGHT as f32;
+    let mut draw = Draw::new(WIDTH, HEIGHT, scene.materials.clone());
+    draw.clear_depth_buffer();
+    let mut frame = vec![0; 4 * WIDTH * HEIGHT];
+    let size = Vec2::new(WIDTH as f32, HEIGHT as f32);
+    pipeline.render(
+        &mut frame,
+        &mut draw,
+        scene,
+        &camera,
+        &Lighting::default(),
+        size,
+    );
+    (frame, draw)
+}
+
+#[test]
+fn shared_vertices_are_transformed_once() {
+    let scene = load_scene("assets/grass_block/grass_block.obj");
+    let mesh = &scene.meshes[0];
+    let mut pipeline = Pipeline::new();
+    render(
+        &mut pipeline,
+        &scene,
+        Vec3A::new(2.5, 2.5, -2.5),
+        Vec2::new(-35.0, 225.0),
+    );
+
+    // The whole block is in view, so every triangle is visited
+    let stats = pipeline.stats();
+    assert_eq!(stats.corners, 3 * mesh.indices.len());
+    assert_eq!(stats.vertices, mesh.vertices.len());
+    assert!(stats.vertices < stats.corners, "{stats:?}");
+}
+
+#[test]
+fn guard_band_clips_fewer_triangles() {
+    // Close enough that many of the castle's triangles cross the screen edges
+    let scene = load_scene("assets/castle/castle.obj");
+    let position = Vec3A::new(0.0, 3.0, -4.0);
+    let rotation = Vec2::new(-10.0, 200.0);
+
+    let mut clipping = Pipeline::new();
+    let (clipped_frame, _) = render(&mut clipping, &scene, position, rotation);
+    let mut guard_band = Pipeline::new();
+    guard_band.guard_band = true;
+    let (guard_band_frame, _) = render(&mut guard_band, &scene, position, rotation);
+
+    let [clipping, guard_band] = [clipping.stats(), guard_band.stats()];
+    assert_eq!(clipping.triangles, guard_band.triangles);
+    assert!(clipping.clipped > clipping.triangles, "{clipping:?}");
+    assert!(guard_band.clipped < clipping.clipped, "{guard_band:?}");
+
+    // Only pixels along the seams of split triangles may change slightly
+    let differing = clipped_frame
+        .chunks_exact(4)
+        .zip(guard_band_frame.chunks_exact(4))
+        .filter(|(a, b)| a.iter().zip(*b).any(|(a, b)| a.abs_diff(*b) > 8))
+        .count();
+    assert!(
+        differing <= WIDTH * HEIGHT / 200,
+        "{differing} pixels differ"
+    );
+
+    // Rendering offscreen with the guard band enabled by the settings gives
+    // the same image as enabling it on the pipeline
+    let mut camera = Camera::new(
+        position,
+        Vec2::new(rotation.x.to_radians(), rotation.y.to_radians()),
+    );
+    camera.aspect_ratio = WIDTH as f32 / HEIGHT as f32;
+    let settings = RenderSettings {
+        guard_band: true,
+        ..RenderSettings::default()
+    };
+    let image = headless::render_to_image(
+        &scene,
+        &camera,
+        &Lighting::default(),
+        &settings,
+        UVec2::new(WIDTH as u32, HEIGHT as u32),
+        [0; 4],
+    );
+    assert!(image.as_raw() == &guard_band_frame);
+}
+
+#[test]
+fn bvh_skips_triangles_behind_the_camera() {
+    // A row of small triangles along the z axis, half of them behind a
+    // camera at the origin looking down the positive z axis
+    let vertices: Vec<Vertex> = (-100..100)
+        .flat_map(|i| {
+            let z = i as f32 * 0.5;
+            [
+                Vec3A::new(-0.1, -0.1, z),
+                Vec3A::new(0.1, -0.1, z),
+                Vec3A::new(0.0, 0.1, z + 0.2),
+            ]
+        })
+        .map(|position| Vertex {
+            position,
+            ..Vertex::default()
+        })
+        .collect();
+    let indices: Vec<[u32; 3]> = (0..vertices.len() as u32 / 3)
+        .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+        .collect();
+    let bvh = Bvh::new(&vertices, &indices);
+
+    let mut camera = Camera::new(Vec3A::ZERO, Vec2::new(0.0, PI));
+    camera.aspect_ratio = 1.0;
+    let frustum = Frustum::from_matrix(camera.view_projection_matrix());
+    let mut visited = vec![];
+    bvh.traverse(&frustum, camera.position, |triangles| {
+        visited.extend_from_slice(triangles);
+    });
+
+    let farthest_z = |triangle: u32| {
+        indices[triangle as usize]
+            .map(|i| vertices[i as usize].position.z)
+            .into_iter()
+            .fold(f32::MIN, f32::max)
+    };
+    // Leaves are only kept whole, so one crossing the camera may be visited
+    let behind = visited
+        .iter()
+        .filter(|&&triangle| farthest_z(triangle) < -2.5)
+        .count();
+    assert_eq!(
+        behind, 0,
+        "Visited triangles behind the camera: {visited:?}"
+    );
+    for triangle in 0..indices.len() as u32 {
+        if farthest_z(triangle) > 2.5 {
+            assert!(visited.contains(&triangle), "Skipped triangle {triangle}");
+        }
+    }
+    assert!(visited.len() < indices.len() / 2 + 10, "{visited:?}");
+}
+
+/// Returns a triangle with its corners at `corners` in pixels, all at `depth`.
+fn flat_triangle(corners: [[f32; 2]; 3], depth: f32) -> ScreenTriangle {
+    ScreenTriangle {
+        vertices: corners.map(|[x, y]| ScreenVertex {
+            position: Vec3A::new(x, y, depth),
+            inverse_w: 1.0,
+            color: Vec4::ONE,
+            ..ScreenVertex::default()
+        }),
+        ..ScreenTriangle::default()
+    }
+}
+
+#[test]
+fn hidden_triangle_is_occluded() {
+    let size = WIDTH as f32 + HEIGHT as f32;
+    // Screen depth goes from -1 at the near plane to 0 at the far plane
+    let occluder = flat_triangle([[-1.0, -1.0], [2.0 * size, -1.0], [-1.0, 2.0 * size]], -0.9);
+    let hidden = flat_triangle([[20.0, 20.0], [40.0, 20.0], [20.0, 40.0]], -0.5);
+
+    let mut draw = Draw::new(WIDTH, HEIGHT, Materials::new());
+    draw.clear_depth_buffer();
+    let mut frame = vec![0; 4 * WIDTH * HEIGHT];
+    draw.draw_triangles(
+        &mut frame,
+        &[occluder, hidden],
+        &Lighting::default(),
+        Vec3A::ZERO,
+    );
+    assert_eq!(
+        draw.stats(),
+        DrawStats {
+            triangles: 2,
+            occluded: 1,
+        }
+    );
+}
+
+#[test]
+fn material_alpha_cutoff_overrides_blending() {
+    // A 40% opaque white triangle over a black frame, drawn with and without
+    // cutoffs on either side of its opacity
+    let background = [0, 0, 0, 255];
+    let center = 4 * (30 * WIDTH + 30);
+    let cases = [(None, 102), (Some(0.3), 255), (Some(0.5), 0)];
+    for (alpha_cutoff, red) in cases {
+        let mut materials = Materials::new();
+        let material = materials.insert(
+            "faded",
+            Material {
+                dissolve: 0.4,
+                alpha_cutoff,
+                illumination: 0,
+                ..Material::default()
+            },
+        );
+        let mut draw = Draw::new(WIDTH, HEIGHT, materials);
+        draw.clear_depth_buffer();
+        let mut frame = background.repeat(WIDTH * HEIGHT);
+        let triangle = ScreenTriangle {
+            material,
+            ..flat_triangle([[10.0, 10.0], [60.0, 10.0], [10.0, 60.0]], -0.5)
+        };
+        draw.draw_triangles(&mut frame, &[triangle], &Lighting::default(), Vec3A::ZERO);
+        assert_eq!(frame[center], red, "{alpha_cutoff:?}");
+    }
+}
+
+#[test]
+fn vertex_colors_are_interpolated() {
+    // Red fading to transparent green across a triangle over a black frame
+    let mut triangle = flat_triangle([[0.0, 0.0], [100.0, 0.0], [0.0, 100.0]], -0.5);
+    triangle.vertices[0].color = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    for vertex in &mut triangle.vertices[1..] {
+        vertex.color = Vec4::new(0.0, 1.0, 0.0, 0.0);
+    }
+    let mut materials = Materials::new();
+    materials[MaterialId::DEFAULT].illumination = 0;
+    let mut draw = Draw::new(WIDTH, HEIGHT, materials);
+    draw.clear_depth_buffer();
+    let mut frame = [0, 0, 0, 255].repeat(WIDTH * HEIGHT);
+    draw.draw_triangles(&mut frame, &[triangle], &Lighting::default(), Vec3A::ZERO);
+
+    // Partly transparent vertices make the triangle blended, so it is
+    // nearly opaque red at the red corner and fades out towards the far edge
+    let pixel = |x: usize, y: usize| &frame[4 * (y * WIDTH + x)..4 * (y * WIDTH + x) + 3];
+    let corner = pixel(0, 0);
+    assert!(corner[0] > 240 && corner[1] < 8, "{corner:?}");
+    let middle = pixel(25, 25);
+    assert!(
+        middle[0] > 0 && middle[0] < 255 && middle[1] > 0,
+        "{middle:?}"
+    );
+    assert!(pixel(45, 45)[0] < middle[0], "{:?}", pixel(45, 45));
+}